@@ -0,0 +1,477 @@
+use crate::{
+    config::Config,
+    modal::modal,
+    provision::{self, Bundle, Conflict, Resolution},
+    secrets::Secret,
+    styles::ui_box,
+};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString}, Argon2,
+};
+use iced::{
+    widget::{button, checkbox, column, container, operation, pick_list, row, scrollable, svg, text, text_input}, Center, Element, Fill, Shrink, Task,
+    Theme,
+};
+
+#[derive(Debug)]
+pub struct State {
+    unlocked: bool,
+    /// Whether the panel is out of the way while admin mode goes on, so that clusters can be
+    /// edited on the screen below.
+    hidden: bool,
+    password: Secret,
+    error: Option<String>,
+    pending: bool,
+    /// Path of the provisioning bundle to import.
+    import_path: String,
+    import: Option<Import>,
+    /// Outcome of the last import, or why it failed.
+    import_status: Option<String>,
+    /// File the config is exported to, with secrets redacted.
+    export_path: String,
+    export_status: Option<String>,
+}
+
+/// Bundle that was read and is waiting for its conflicts to be resolved.
+#[derive(Debug)]
+struct Import {
+    bundle: Bundle,
+    conflicts: Vec<(Conflict, Resolution)>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Message {
+    Password(Secret),
+    Submit,
+    Verified(bool),
+    SetPassword,
+    PasswordHashed(Option<String>),
+    ImportPath(String),
+    ReadBundle,
+    BundleRead(Result<Bundle, String>),
+    Resolve(usize, Resolution),
+    Import,
+    CancelImport,
+    RememberPasswords(bool),
+    ExportPath(String),
+    Export,
+    Exported(Result<(), String>),
+    Quit,
+    Hide,
+    Show,
+    Close,
+}
+
+#[derive(Debug)]
+pub enum Action {
+    Run(Task<Message>),
+    SaveConfig,
+    Quit,
+    Close,
+    None,
+}
+
+impl State {
+    const PASSWORD_ID: &str = "admin_password";
+
+    pub fn new() -> (Self, Task<Message>) {
+        (
+            Self {
+                unlocked: false,
+                hidden: false,
+                password: Secret::default(),
+                error: None,
+                pending: false,
+                import_path: String::new(),
+                import: None,
+                import_status: None,
+                export_path: String::new(),
+                export_status: None,
+            },
+            operation::focus(Self::PASSWORD_ID),
+        )
+    }
+
+    pub const fn unlocked(&self) -> bool {
+        self.unlocked
+    }
+
+    /// Whether the panel is hidden in admin mode, leaving the screen below usable.
+    pub const fn hidden(&self) -> bool {
+        self.hidden
+    }
+
+    pub fn update(&mut self, message: Message, config: &mut Config) -> Action {
+        match message {
+            Message::Password(password) => {
+                self.password = password;
+                self.error = None;
+                Action::None
+            }
+            Message::Submit => {
+                if self.unlocked || self.pending {
+                    return Action::None;
+                }
+
+                // A fresh install has to choose a password before anything can be changed, or
+                // anyone could quit the kiosk
+                let Some(hash) = config.kiosk.admin_password.clone() else {
+                    return self.update(Message::SetPassword, config);
+                };
+
+                // Hashing is deliberately slow, so keep it off the UI thread
+                let password = std::mem::take(&mut self.password);
+                self.pending = true;
+                Action::Run(Task::perform(
+                    async move { verify_password(&hash, password.expose()) },
+                    Message::Verified,
+                ))
+            }
+            Message::Verified(valid) => {
+                self.pending = false;
+
+                if valid {
+                    self.unlocked = true;
+                    Action::None
+                } else {
+                    self.error = Some("Incorrect password".to_owned());
+                    Action::Run(operation::focus(Self::PASSWORD_ID))
+                }
+            }
+            Message::SetPassword => {
+                let first = config.kiosk.admin_password.is_none();
+                if !(self.unlocked || first) || self.pending {
+                    return Action::None;
+                }
+
+                if self.password.is_empty() {
+                    self.error = Some("Empty password is not valid".to_owned());
+                    return Action::None;
+                }
+
+                let password = std::mem::take(&mut self.password);
+                self.pending = true;
+                Action::Run(Task::perform(
+                    async move { hash_password(password.expose()) },
+                    Message::PasswordHashed,
+                ))
+            }
+            Message::PasswordHashed(hash) => {
+                self.pending = false;
+
+                if let Some(hash) = hash {
+                    config.kiosk.admin_password = Some(hash);
+                    self.unlocked = true;
+                    Action::SaveConfig
+                } else {
+                    self.error = Some("Failed to hash password".to_owned());
+                    Action::None
+                }
+            }
+            Message::ImportPath(_)
+            | Message::ReadBundle
+            | Message::BundleRead(_)
+            | Message::Resolve(..)
+            | Message::Import
+            | Message::CancelImport => self.update_import(message, config),
+            Message::RememberPasswords(allowed) => {
+                if !self.unlocked {
+                    return Action::None;
+                }
+
+                config.kiosk.remember_passwords = allowed;
+                Action::SaveConfig
+            }
+            Message::ExportPath(path) => {
+                self.export_path = path;
+                self.export_status = None;
+                Action::None
+            }
+            Message::Export => self.export(config),
+            Message::Exported(result) => {
+                self.export_status = Some(match result {
+                    Ok(()) => "Exported with secrets redacted".to_owned(),
+                    Err(err) => err,
+                });
+                Action::None
+            }
+            Message::Quit => {
+                if self.unlocked {
+                    Action::Quit
+                } else {
+                    Action::None
+                }
+            }
+            Message::Hide => {
+                self.hidden = self.unlocked;
+                Action::None
+            }
+            Message::Show => {
+                self.hidden = false;
+                Action::Run(operation::focus(Self::PASSWORD_ID))
+            }
+            Message::Close => Action::Close,
+        }
+    }
+
+    /// Writes the config with secrets redacted to the export path.
+    fn export(&self, config: &Config) -> Action {
+        if !self.unlocked {
+            return Action::None;
+        }
+
+        let path = self.export_path.trim().to_owned();
+        let config = config.clone();
+        Action::Run(Task::perform(
+            async move {
+                let export = provision::export(&config)?;
+                std::fs::write(&path, export).map_err(|err| format!("Can't write {path}: {err}"))
+            },
+            Message::Exported,
+        ))
+    }
+
+    fn update_import(&mut self, message: Message, config: &mut Config) -> Action {
+        if !self.unlocked {
+            return Action::None;
+        }
+
+        match message {
+            Message::ImportPath(path) => {
+                self.import_path = path;
+                self.import_status = None;
+            }
+            Message::ReadBundle => {
+                let path = self.import_path.trim().to_owned();
+                self.import = None;
+                self.import_status = None;
+
+                return Action::Run(Task::perform(
+                    async move { Bundle::read(&path) },
+                    Message::BundleRead,
+                ));
+            }
+            Message::BundleRead(Ok(bundle)) => {
+                let conflicts = bundle
+                    .conflicts(config)
+                    .into_iter()
+                    .map(|conflict| (conflict, Resolution::default()))
+                    .collect();
+                self.import = Some(Import { bundle, conflicts });
+            }
+            Message::BundleRead(Err(err)) => self.import_status = Some(err),
+            Message::Resolve(idx, resolution) => {
+                if let Some((_, current)) = self
+                    .import
+                    .as_mut()
+                    .and_then(|import| import.conflicts.get_mut(idx))
+                {
+                    *current = resolution;
+                }
+            }
+            Message::Import => {
+                let Some(Import { bundle, conflicts }) = self.import.take() else {
+                    return Action::None;
+                };
+
+                let report = bundle.merge(config, |conflict| {
+                    conflicts
+                        .iter()
+                        .find(|(other, _)| other == conflict)
+                        .map(|(_, resolution)| *resolution)
+                        .unwrap_or_default()
+                });
+                self.import_status = Some(format!("Imported: {report}"));
+                return Action::SaveConfig;
+            }
+            Message::CancelImport => self.import = None,
+            _ => {}
+        }
+
+        Action::None
+    }
+
+    fn view_import(&self) -> Element<'_, Message> {
+        let path = row![
+            text_input("Provisioning bundle", &self.import_path)
+                .on_input(Message::ImportPath)
+                .on_submit(Message::ReadBundle),
+            button("Read").on_press_maybe(
+                (!self.import_path.trim().is_empty()).then_some(Message::ReadBundle),
+            ),
+        ]
+        .spacing(10);
+
+        let import = self.import.as_ref().map(|import| {
+            let bundle = &import.bundle;
+            let conflicts =
+                import
+                    .conflicts
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, (conflict, resolution))| {
+                        row![
+                            text(format!("{conflict} already exists")).width(Fill),
+                            pick_list(Resolution::ALL, Some(*resolution), move |resolution| {
+                                Message::Resolve(idx, resolution)
+                            }),
+                        ]
+                        .spacing(10)
+                        .align_y(Center)
+                        .into()
+                    });
+
+            column![
+                text(format!(
+                    "{} clusters, {} users and {} viewer profiles",
+                    bundle.clusters.len(),
+                    bundle.users.len(),
+                    bundle.viewer_profiles.len()
+                )),
+                scrollable(column(conflicts).spacing(4)).height(Shrink),
+                row![
+                    button("Import").on_press(Message::Import),
+                    button("Cancel")
+                        .style(button::secondary)
+                        .on_press(Message::CancelImport),
+                ]
+                .spacing(10),
+            ]
+            .spacing(6)
+            .align_x(Center)
+        });
+
+        column![path, import, self.import_status.as_deref().map(text)]
+            .spacing(6)
+            .align_x(Center)
+            .into()
+    }
+
+    fn view_export(&self) -> Element<'_, Message> {
+        column![
+            row![
+                text_input("Export to", &self.export_path)
+                    .on_input(Message::ExportPath)
+                    .on_submit(Message::Export),
+                button("Export").on_press_maybe(
+                    (!self.export_path.trim().is_empty()).then_some(Message::Export),
+                ),
+            ]
+            .spacing(10),
+            self.export_status.as_deref().map(text),
+        ]
+        .spacing(6)
+        .align_x(Center)
+        .into()
+    }
+
+    pub fn view<'a>(&'a self, config: &'a Config) -> Element<'a, Message> {
+        if self.hidden {
+            return container(
+                container(
+                    row![
+                        text("Admin mode"),
+                        button("Admin panel").on_press(Message::Show),
+                        button("Leave admin mode")
+                            .style(button::secondary)
+                            .on_press(Message::Close),
+                    ]
+                    .spacing(10)
+                    .align_y(Center),
+                )
+                .padding([5, 20])
+                .style(ui_box),
+            )
+            .center_x(Fill)
+            .padding(10)
+            .into();
+        }
+
+        let error_message = self.error.as_ref().map(|error| {
+            text(error.as_str()).style(|theme: &Theme| text::Style {
+                color: Some(theme.palette().danger),
+            })
+        });
+
+        let content = if self.unlocked {
+            let password_input = text_input("New admin password", self.password.expose())
+                .on_input(|text| Message::Password(Secret::new(text)))
+                .on_submit(Message::SetPassword)
+                .secure(true)
+                .id(Self::PASSWORD_ID);
+
+            let set_password = button("Set").on_press_maybe(
+                (!self.pending && !self.password.is_empty()).then_some(Message::SetPassword),
+            );
+
+            column![
+                text("Admin mode").size(25),
+                text(format!("Emergency chord: {}", config.kiosk.emergency_chord)),
+                row![password_input, set_password].spacing(10),
+                error_message,
+                checkbox(config.kiosk.remember_passwords)
+                    .label("Let users remember their password")
+                    .on_toggle(Message::RememberPasswords),
+                self.view_import(),
+                self.view_export(),
+                row![
+                    button("Edit clusters")
+                        .style(button::secondary)
+                        .on_press(Message::Hide),
+                    button("Quit Antechamber").on_press(Message::Quit),
+                ]
+                .spacing(10),
+            ]
+        } else {
+            let first = config.kiosk.admin_password.is_none();
+
+            column![
+                text("Unlock admin mode").size(25),
+                first.then(|| text("Choose the admin password first")),
+                text_input(
+                    if first {
+                        "New admin password"
+                    } else {
+                        "Admin password"
+                    },
+                    self.password.expose()
+                )
+                .on_input(|text| Message::Password(Secret::new(text)))
+                .on_submit(Message::Submit)
+                .secure(true)
+                .id(Self::PASSWORD_ID),
+                error_message,
+                button(if first { "Set and unlock" } else { "Unlock" })
+                    .on_press_maybe((!self.pending).then_some(Message::Submit)),
+            ]
+        };
+
+        modal(
+            container(content.spacing(10).align_x(Center).width(Fill)).center(400),
+            Message::Close,
+        )
+        .padding(20)
+        .style(ui_box)
+        .svg_style(|theme, _| svg::Style {
+            color: Some(theme.extended_palette().primary.base.text),
+        })
+        .into()
+    }
+}
+
+fn hash_password(password: &str) -> Option<String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .ok()
+        .map(|hash| hash.to_string())
+}
+
+fn verify_password(hash: &str, password: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
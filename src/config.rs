@@ -1,9 +1,17 @@
-use crate::NAME_LOWER;
+use crate::{
+    proxmox::{ClusterNode, GuestKind},
+    secrets::{self, Secret, SecretStore},
+    usb::UsbDevice,
+    NAME_LOWER,
+};
 use directories::ProjectDirs;
+use iced::keyboard::{key::Named, Key, Modifiers};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     fmt::{Display, Formatter},
-    fs::File,
+    fs,
+    io,
     net::IpAddr,
     path::{Path, PathBuf},
 };
@@ -11,46 +19,110 @@ use std::{
 #[derive(Clone, Debug)]
 pub struct ConfigManager {
     pub config: Config,
+    /// Where the config is saved, which is nowhere when it couldn't be loaded, so that the file
+    /// isn't overwritten with a config that doesn't match it.
+    storage: Option<Storage>,
+}
+
+#[derive(Clone, Debug)]
+struct Storage {
     config_dir: PathBuf,
+    /// API tokens and passwords, which the config file only refers to.
+    secrets: SecretStore,
 }
 
 impl ConfigManager {
-    // TODO: Better error handling
-    pub fn from_config(config: Config) -> Option<Self> {
-        let config_dir = Self::config_dir()?;
-
-        Some(Self { config, config_dir })
-    }
-
-    // TODO: Better error handling
-    pub fn load() -> Option<Self> {
-        let config_dir = Self::config_dir()?;
-        let config_file = File::options()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(false)
-            .open(Self::config_file_path(&config_dir))
-            .ok()?;
-
-        Some(Self {
-            config: serde_yaml::from_reader(config_file).ok()?,
-            config_dir,
-        })
-    }
-
-    #[must_use]
-    // TODO: Better error handling
-    pub fn save(&self) -> bool {
-        // TODO: consider creating dir if missing
-        let Ok(config_file) = File::create(Self::config_file_path(&self.config_dir)) else {
-            return false;
+    /// Config that is never saved.
+    pub const fn unsaved(config: Config) -> Self {
+        Self {
+            config,
+            storage: None,
+        }
+    }
+
+    pub fn load() -> Result<Self, Error> {
+        let config_dir = Self::config_dir().ok_or(Error::NoConfigDir)?;
+        let config = match fs::read_to_string(Self::config_file_path(&config_dir)) {
+            Ok(config) => config,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        // There's no config until it's first saved
+        let mut config = if config.trim().is_empty() {
+            Config::default()
+        } else {
+            serde_yaml::from_str(&config).map_err(Error::Format)?
+        };
+
+        let secrets = SecretStore::open(&config_dir).map_err(Error::Secrets)?;
+
+        let mut plaintext = false;
+        for secret in config.users.iter_mut().flat_map(User::secrets_mut) {
+            if secrets::is_reference(secret.expose()) {
+                // Secrets missing from the store have to be entered again, rather than making the
+                // whole config unusable
+                *secret = secrets.get(secret.expose()).cloned().unwrap_or_default();
+            } else {
+                plaintext = true;
+            }
+        }
+        for user in &mut config.users {
+            if user
+                .remembered_password
+                .as_ref()
+                .is_some_and(Secret::is_empty)
+            {
+                user.remembered_password = None;
+            }
+        }
+
+        let mut manager = Self {
+            config,
+            storage: Some(Storage {
+                config_dir,
+                secrets,
+            }),
+        };
+
+        // Tokens from before the secrets file existed are moved there right away
+        if plaintext {
+            manager.save()?;
+        }
+
+        Ok(manager)
+    }
+
+    pub fn save(&mut self) -> Result<(), Error> {
+        let Some(storage) = &mut self.storage else {
+            return Err(Error::Unsaved);
         };
 
-        serde_yaml::to_writer(config_file, &self.config).is_ok()
+        // Passwords are forgotten as soon as the policy stops allowing them
+        if !self.config.kiosk.remember_passwords {
+            for user in &mut self.config.users {
+                user.remembered_password = None;
+            }
+        }
+
+        let mut config = self.config.clone();
+        let mut references = vec![];
+        for secret in config.users.iter_mut().flat_map(User::secrets_mut) {
+            let reference = storage.secrets.insert(std::mem::take(secret));
+            *secret = Secret::new(reference.clone());
+            references.push(reference);
+        }
+        storage.secrets.retain(&references);
+
+        fs::create_dir_all(&storage.config_dir)?;
+        // Secrets go first, so the config never refers to ones that weren't written
+        storage.secrets.save().map_err(Error::Secrets)?;
+        let config_file = secrets::create_private(&Self::config_file_path(&storage.config_dir))?;
+
+        serde_yaml::to_writer(config_file, &config).map_err(Error::Format)
     }
 
-    fn config_dir() -> Option<PathBuf> {
+    pub fn config_dir() -> Option<PathBuf> {
         const DEBUG_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/dev_conf");
 
         if cfg!(feature = "dev_mode") {
@@ -60,6 +132,10 @@ impl ConfigManager {
         }
     }
 
+    pub fn config_file() -> Option<PathBuf> {
+        Self::config_dir().map(|config_dir| Self::config_file_path(&config_dir))
+    }
+
     fn config_file_path(config_dir: &Path) -> PathBuf {
         const CONFIG_FILE_NAME: &str = "config.yaml";
 
@@ -67,6 +143,35 @@ impl ConfigManager {
     }
 }
 
+#[derive(Debug)]
+pub enum Error {
+    /// There's no home directory to keep the config in.
+    NoConfigDir,
+    Io(io::Error),
+    Format(serde_yaml::Error),
+    Secrets(secrets::Error),
+    /// The config couldn't be loaded, so saving it would overwrite the one on disk.
+    Unsaved,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoConfigDir => write!(f, "There's no directory for the config"),
+            Self::Io(err) => write!(f, "Can't access the config: {err}"),
+            Self::Format(err) => write!(f, "Invalid config: {err}"),
+            Self::Secrets(err) => err.fmt(f),
+            Self::Unsaved => write!(f, "The config isn't saved, since it couldn't be loaded"),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
 // TODO: Validate Config creation
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Config {
@@ -74,13 +179,119 @@ pub struct Config {
     pub clusters: Vec<Cluster>,
     pub default_user: Option<usize>,
     pub users: Vec<User>,
+    /// Arguments of the viewer for guests without a viewer profile.
     pub viewer_args: Vec<String>,
+    #[serde(default)]
+    pub viewer_profiles: BTreeMap<String, ViewerProfile>,
+    /// Options written to the .vv file of every connection, replacing those sent by Proxmox, such as
+    /// `fullscreen: "1"` or `usb-filter`.
+    #[serde(default)]
+    pub vv_overrides: BTreeMap<String, String>,
+    /// Command opening the console of containers, usually a terminal emulator connecting to the
+    /// Unix socket `{socket}` that the console is relayed to, or xterm running socat when empty.
+    /// `{node}`, `{vmid}` and `{name}` are replaced with those of the container.
+    #[serde(default)]
+    pub terminal_command: Vec<String>,
+    /// Start stopped guests without asking when connecting to them.
+    #[serde(default)]
+    pub auto_start_guests: bool,
+    #[serde(default)]
+    pub kiosk: Kiosk,
+    #[serde(default)]
+    pub usb: UsbPolicy,
+    /// Subnet like `192.168.1.0/24` or SRV record like `_pve._tcp.example.com` searched for
+    /// servers when adding a cluster.
+    #[serde(default)]
+    pub discovery: String,
 }
 
-#[derive(Clone, Debug, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
+impl Config {
+    /// Name of the viewer profile for a guest, where the profile of the guest wins over that of
+    /// the user, which wins over that of the cluster.
+    pub fn viewer_profile_name(&self, cluster: usize, user: usize, vmid: u32) -> Option<&str> {
+        let cluster = &self.clusters[cluster];
+
+        cluster
+            .guest_viewer_profiles
+            .get(&vmid)
+            .or(self.users[user].viewer_profile.as_ref())
+            .or(cluster.viewer_profile.as_ref())
+            .map(String::as_str)
+    }
+
+    /// Removes the cluster at `idx`, keeping the default cluster pointing at the same one.
+    pub fn remove_cluster(&mut self, idx: usize) {
+        self.clusters.remove(idx);
+
+        self.default_cluster = shift_index(self.default_cluster, idx);
+    }
+
+    /// Removes the user at `idx`, keeping the default user pointing at the same one.
+    pub fn remove_user(&mut self, idx: usize) {
+        self.users.remove(idx);
+        self.default_user = shift_index(self.default_user, idx);
+    }
+
+    /// Renames a cluster, along with the guests of it that users remember.
+    pub fn rename_cluster(&mut self, idx: usize, name: String) {
+        let old = std::mem::replace(&mut self.clusters[idx].name, name);
+
+        for user in &mut self.users {
+            for guest in user.recent.iter_mut().chain(&mut user.favorites) {
+                if guest.cluster == old {
+                    guest.cluster.clone_from(&self.clusters[idx].name);
+                }
+            }
+        }
+    }
+
+    /// Viewer profile called `name`, falling back to [`Self::viewer_args`] without one.
+    pub fn viewer_profile(&self, name: Option<&str>) -> ViewerProfile {
+        name.and_then(|name| self.viewer_profiles.get(name))
+            .cloned()
+            .unwrap_or_else(|| ViewerProfile {
+                args: self.viewer_args.clone(),
+                ..ViewerProfile::default()
+            })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Cluster {
     pub name: String,
     pub hosts: Vec<Host>,
+    /// Keeps the hosts as entered, instead of adding new members of the cluster after each login.
+    #[serde(default)]
+    pub fixed_hosts: bool,
+    #[serde(default)]
+    pub viewer_profile: Option<String>,
+    /// Viewer profiles of single guests, by VMID.
+    #[serde(default)]
+    pub guest_viewer_profiles: BTreeMap<u32, String>,
+}
+
+impl Cluster {
+    /// Adds the members of the cluster that aren't hosts yet, returning whether any were added.
+    ///
+    /// Nodes are matched to hosts by their address or by name, and new ones are added at the end,
+    /// on the port of the first host. Hosts are never removed, since the addresses nodes use
+    /// within the cluster aren't necessarily reachable from here, unlike the hosts.
+    pub fn update_hosts(&mut self, nodes: &[ClusterNode]) -> bool {
+        let port = self.hosts.first().map_or(Host::DEFAULT_PORT, Host::port);
+        let before = self.hosts.len();
+
+        for node in nodes {
+            let Some(address) = node.ip.as_deref().and_then(Address::parse) else {
+                continue;
+            };
+
+            if !self.hosts.iter().any(|host| host.is_node(node)) {
+                self.hosts.push(Host::new(address, port));
+            }
+        }
+
+        self.hosts.len() != before
+    }
 }
 
 impl Display for Cluster {
@@ -93,6 +304,60 @@ impl Display for Cluster {
 pub struct Host {
     address: Address,
     port: u16,
+    /// SHA-256 fingerprint of the certificate of the host, which is then the only one trusted.
+    ///
+    /// Hosts without one need a certificate signed by a trusted CA.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
+}
+
+impl Host {
+    /// Port the Proxmox API listens on, unless configured otherwise.
+    pub const DEFAULT_PORT: u16 = 8006;
+
+    /// Whether the host is `node`, going by its address, or by its name for hosts given by name.
+    pub fn is_node(&self, node: &ClusterNode) -> bool {
+        match &self.address {
+            Address::Ip(_) => {
+                node.ip.as_deref().and_then(Address::parse).as_ref() == Some(&self.address)
+            }
+            Address::Dns(name) => name
+                .split('.')
+                .next()
+                .is_some_and(|label| label.eq_ignore_ascii_case(&node.name)),
+        }
+    }
+
+    pub const fn new(address: Address, port: u16) -> Self {
+        Self {
+            address,
+            port,
+            fingerprint: None,
+        }
+    }
+
+    pub fn with_fingerprint(mut self, fingerprint: Option<String>) -> Self {
+        self.fingerprint = fingerprint;
+        self
+    }
+
+    pub const fn address(&self) -> &Address {
+        &self.address
+    }
+
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn fingerprint(&self) -> Option<&str> {
+        self.fingerprint.as_deref()
+    }
+}
+
+impl Display for Host {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.address, self.port)
+    }
 }
 
 #[derive(Clone, Debug, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
@@ -101,10 +366,94 @@ pub enum Address {
     Dns(String),
 }
 
+impl Address {
+    /// Parses an IP address, with IPv6 addresses optionally in brackets, or a DNS name.
+    pub fn parse(address: &str) -> Option<Self> {
+        let address = address.trim();
+        let ip = address
+            .strip_prefix('[')
+            .and_then(|address| address.strip_suffix(']'))
+            .unwrap_or(address);
+
+        if let Ok(ip) = ip.parse() {
+            return Some(Self::Ip(ip));
+        }
+
+        let valid = !address.is_empty()
+            && address.split('.').all(|label| {
+                !label.is_empty()
+                    && !label.starts_with('-')
+                    && label
+                        .chars()
+                        .all(|char| char.is_ascii_alphanumeric() || char == '-')
+            });
+
+        valid.then(|| Self::Dns(address.to_owned()))
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ip(IpAddr::V6(ip)) => write!(f, "[{ip}]"),
+            Self::Ip(IpAddr::V4(ip)) => ip.fmt(f),
+            Self::Dns(name) => name.fmt(f),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
 pub struct User {
     pub display_name: String,
     pub auth_method: AuthMethod,
+    #[serde(default)]
+    pub guest_list: GuestList,
+    /// Guests most recently connected to, most recent first.
+    #[serde(default)]
+    pub recent: Vec<GuestRef>,
+    #[serde(default)]
+    pub favorites: Vec<GuestRef>,
+    #[serde(default)]
+    pub viewer_profile: Option<String>,
+    /// Outputs of the client the viewer spans, replacing those of the viewer profile.
+    #[serde(default)]
+    pub monitors: Vec<String>,
+    /// Password the user chose to have remembered, if the kiosk policy allows it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remembered_password: Option<Secret>,
+}
+
+impl User {
+    const RECENT_LIMIT: usize = 20;
+
+    pub fn record_connection(&mut self, guest: GuestRef) {
+        self.recent.retain(|recent| *recent != guest);
+        self.recent.insert(0, guest);
+        self.recent.truncate(Self::RECENT_LIMIT);
+    }
+
+    /// Drops favorites and recent guests of `cluster` that aren't among `vmids` anymore.
+    ///
+    /// Returns whether anything was dropped.
+    pub fn forget_missing(&mut self, cluster: &str, vmids: &[u32]) -> bool {
+        let keep = |guest: &GuestRef| guest.cluster != cluster || vmids.contains(&guest.vmid);
+        let before = self.recent.len() + self.favorites.len();
+
+        self.recent.retain(keep);
+        self.favorites.retain(keep);
+
+        before != self.recent.len() + self.favorites.len()
+    }
+
+    /// API token and remembered password, which are kept in the secrets file.
+    pub fn secrets_mut(&mut self) -> impl Iterator<Item = &mut Secret> {
+        let token = match &mut self.auth_method {
+            AuthMethod::ApiToken(token) => Some(token),
+            AuthMethod::Password { .. } => None,
+        };
+
+        token.into_iter().chain(&mut self.remembered_password)
+    }
 }
 
 impl Display for User {
@@ -113,16 +462,539 @@ impl Display for User {
     }
 }
 
+/// How guests are shown by the viewer.
+#[derive(Clone, Debug, Default, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewerProfile {
+    /// Viewer to run instead of `remote-viewer`, which has to understand .vv files.
+    pub binary: Option<String>,
+    pub args: Vec<String>,
+    pub fullscreen: bool,
+    /// Displays of the guest mapped to monitors of the client, such as `1:2;2:1`.
+    pub monitor_mapping: Option<String>,
+    /// Outputs of the client the viewer spans in fullscreen, replacing `monitor_mapping`.
+    pub monitors: Vec<String>,
+    /// Share USB devices with the guest as soon as they are plugged in.
+    pub usb_auto_share: bool,
+    pub smartcard: bool,
+    pub audio: Audio,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Audio {
+    #[default]
+    Full,
+    /// Sound of the guest is played, but the microphone isn't shared.
+    PlaybackOnly,
+    Off,
+}
+
+/// Guest identified across clusters.
+#[derive(Clone, Debug, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuestRef {
+    pub cluster: String,
+    pub vmid: u32,
+}
+
+/// How the guest list on the connect screen is filtered and sorted.
+#[derive(Clone, Debug, Default, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuestList {
+    /// Nodes to show guests of, all nodes when empty.
+    pub nodes: Vec<String>,
+    /// Kinds of guest to show, all kinds when empty.
+    pub kinds: Vec<GuestKind>,
+    pub state: StateFilter,
+    pub sort: GuestSort,
+    pub grouping: GuestGrouping,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StateFilter {
+    #[default]
+    All,
+    Running,
+    Stopped,
+}
+
+impl StateFilter {
+    pub const ALL: [Self; 3] = [Self::All, Self::Running, Self::Stopped];
+}
+
+impl Display for StateFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::All => write!(f, "All"),
+            Self::Running => write!(f, "Running"),
+            Self::Stopped => write!(f, "Stopped"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GuestSort {
+    #[default]
+    Name,
+    Vmid,
+    LastUsed,
+}
+
+impl GuestSort {
+    pub const ALL: [Self; 3] = [Self::Name, Self::Vmid, Self::LastUsed];
+}
+
+impl Display for GuestSort {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Name => write!(f, "Name"),
+            Self::Vmid => write!(f, "VMID"),
+            Self::LastUsed => write!(f, "Last used"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GuestGrouping {
+    #[default]
+    None,
+    Pool,
+    Tag,
+    Node,
+}
+
+impl GuestGrouping {
+    pub const ALL: [Self; 4] = [Self::None, Self::Pool, Self::Tag, Self::Node];
+}
+
+impl Display for GuestGrouping {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "None"),
+            Self::Pool => write!(f, "Pool"),
+            Self::Tag => write!(f, "Tag"),
+            Self::Node => write!(f, "Node"),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AuthMethod {
-    Password { username: String },
-    ApiToken(String),
+    Password {
+        username: String,
+        /// Realm the user is authenticated by, such as `pam` or `pve`.
+        #[serde(default = "AuthMethod::default_realm")]
+        realm: String,
+    },
+    ApiToken(Secret),
+}
+
+impl AuthMethod {
+    pub fn default_realm() -> String {
+        "pam".to_owned()
+    }
+
+    /// Name to log in with, which includes the realm.
+    pub fn login_name(&self) -> Option<String> {
+        match self {
+            Self::Password { username, realm } => Some(format!("{username}@{realm}")),
+            Self::ApiToken(_) => None,
+        }
+    }
 }
 
 impl Default for AuthMethod {
     fn default() -> Self {
         Self::Password {
             username: String::default(),
+            realm: Self::default_realm(),
+        }
+    }
+}
+
+/// Which USB devices of the client may be redirected to guests.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsbPolicy {
+    /// Ask which devices to redirect before connecting to a VM.
+    pub picker: bool,
+    /// Devices that may be redirected, or all devices when empty.
+    ///
+    /// The rules are passed on to the viewer as well, so they apply when it shares devices
+    /// without the picker.
+    pub allow: Vec<UsbRule>,
+    /// Devices that may never be redirected, even when allowed.
+    pub deny: Vec<UsbRule>,
+}
+
+impl UsbPolicy {
+    pub fn allows(&self, device: &UsbDevice) -> bool {
+        (self.allow.is_empty() || self.allow.iter().any(|rule| rule.matches(device)))
+            && !self.deny.iter().any(|rule| rule.matches(device))
+    }
+}
+
+/// Matches USB devices by their IDs and class, where missing fields match any device.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsbRule {
+    pub vendor: Option<u16>,
+    pub product: Option<u16>,
+    pub class: Option<u8>,
+}
+
+impl UsbRule {
+    pub fn matches(&self, device: &UsbDevice) -> bool {
+        self.vendor.is_none_or(|vendor| vendor == device.vendor)
+            && self.product.is_none_or(|product| product == device.product)
+            && self.class.is_none_or(|class| class == device.class)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Kiosk {
+    /// Argon2 hash of the admin password.
+    ///
+    /// Until one is set, unlocking admin mode asks for the password to use from then on.
+    pub admin_password: Option<String>,
+    /// Opens the admin unlock prompt, even while window close requests are being swallowed.
+    pub emergency_chord: KeyChord,
+    /// Lets users have their password remembered, so they can log in with a single click.
+    pub remember_passwords: bool,
+    /// Failed password logins or unlocks of an account after which further attempts are locked
+    /// out, or 0 to never lock accounts out.
+    pub max_login_failures: u32,
+    /// Length of the first lockout, which doubles with every further failed attempt.
+    pub lockout_seconds: u64,
+}
+
+impl Default for Kiosk {
+    fn default() -> Self {
+        Self {
+            admin_password: None,
+            emergency_chord: KeyChord {
+                modifiers: vec![Modifier::Ctrl, Modifier::Alt, Modifier::Shift],
+                key: ChordKey::Named(Named::F12),
+            },
+            remember_passwords: false,
+            max_login_failures: 5,
+            lockout_seconds: 30,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyChord {
+    pub modifiers: Vec<Modifier>,
+    pub key: ChordKey,
+}
+
+impl KeyChord {
+    pub fn matches(&self, key: &Key, modifiers: Modifiers) -> bool {
+        let held = [
+            (Modifier::Ctrl, modifiers.control()),
+            (Modifier::Alt, modifiers.alt()),
+            (Modifier::Shift, modifiers.shift()),
+            (Modifier::Super, modifiers.logo()),
+        ];
+
+        if held
+            .iter()
+            .any(|(modifier, held)| self.modifiers.contains(modifier) != *held)
+        {
+            return false;
+        }
+
+        match (key.as_ref(), &self.key) {
+            (Key::Character(character), ChordKey::Character(chord)) => {
+                character.eq_ignore_ascii_case(chord)
+            }
+            (Key::Named(named), ChordKey::Named(chord)) => named == *chord,
+            _ => false,
         }
     }
 }
+
+/// Key of a chord, written as a single character or the name of a key, such as `F12` or `Escape`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ChordKey {
+    Character(String),
+    Named(Named),
+}
+
+impl ChordKey {
+    /// Named keys that chords can use, by their name in the config.
+    const NAMED: [(&str, Named); 41] = [
+        ("F1", Named::F1),
+        ("F2", Named::F2),
+        ("F3", Named::F3),
+        ("F4", Named::F4),
+        ("F5", Named::F5),
+        ("F6", Named::F6),
+        ("F7", Named::F7),
+        ("F8", Named::F8),
+        ("F9", Named::F9),
+        ("F10", Named::F10),
+        ("F11", Named::F11),
+        ("F12", Named::F12),
+        ("F13", Named::F13),
+        ("F14", Named::F14),
+        ("F15", Named::F15),
+        ("F16", Named::F16),
+        ("F17", Named::F17),
+        ("F18", Named::F18),
+        ("F19", Named::F19),
+        ("F20", Named::F20),
+        ("F21", Named::F21),
+        ("F22", Named::F22),
+        ("F23", Named::F23),
+        ("F24", Named::F24),
+        ("Escape", Named::Escape),
+        ("Enter", Named::Enter),
+        ("Tab", Named::Tab),
+        ("Space", Named::Space),
+        ("Backspace", Named::Backspace),
+        ("Delete", Named::Delete),
+        ("Insert", Named::Insert),
+        ("Home", Named::Home),
+        ("End", Named::End),
+        ("PageUp", Named::PageUp),
+        ("PageDown", Named::PageDown),
+        ("ArrowUp", Named::ArrowUp),
+        ("ArrowDown", Named::ArrowDown),
+        ("ArrowLeft", Named::ArrowLeft),
+        ("ArrowRight", Named::ArrowRight),
+        ("Pause", Named::Pause),
+        ("PrintScreen", Named::PrintScreen),
+    ];
+}
+
+impl Default for ChordKey {
+    fn default() -> Self {
+        Self::Named(Named::F12)
+    }
+}
+
+impl TryFrom<String> for ChordKey {
+    type Error = String;
+
+    fn try_from(key: String) -> Result<Self, Self::Error> {
+        if key.chars().count() == 1 {
+            return Ok(Self::Character(key));
+        }
+
+        Self::NAMED
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&key))
+            .map(|(_, named)| Self::Named(*named))
+            .ok_or_else(|| format!("Unknown key {key}"))
+    }
+}
+
+impl From<ChordKey> for String {
+    fn from(key: ChordKey) -> Self {
+        key.to_string()
+    }
+}
+
+impl Display for ChordKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Character(character) => character.fmt(f),
+            Self::Named(named) => Self::NAMED
+                .iter()
+                .find(|(_, other)| other == named)
+                .map_or("?", |(name, _)| name)
+                .fmt(f),
+        }
+    }
+}
+
+impl Display for KeyChord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{modifier:?}+")?;
+        }
+
+        self.key.fmt(f)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Modifier {
+    Ctrl,
+    Alt,
+    Shift,
+    Super,
+}
+
+/// Index of an item after the one at `removed` was removed, or `None` if it was that one.
+pub const fn shift_index(idx: Option<usize>, removed: usize) -> Option<usize> {
+    match idx {
+        Some(idx) if idx == removed => None,
+        Some(idx) if idx > removed => Some(idx - 1),
+        idx => idx,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(modifiers: Vec<Modifier>, key: ChordKey) -> KeyChord {
+        KeyChord { modifiers, key }
+    }
+
+    #[test]
+    fn chord_matches_held_modifiers_exactly() {
+        let chord = chord(
+            vec![Modifier::Ctrl, Modifier::Alt],
+            ChordKey::Named(Named::F12),
+        );
+        let key = Key::Named(Named::F12);
+
+        assert!(chord.matches(&key, Modifiers::CTRL | Modifiers::ALT));
+        assert!(!chord.matches(&key, Modifiers::CTRL));
+        assert!(!chord.matches(&key, Modifiers::CTRL | Modifiers::ALT | Modifiers::SHIFT));
+        assert!(!chord.matches(&Key::Named(Named::F11), Modifiers::CTRL | Modifiers::ALT));
+    }
+
+    #[test]
+    fn chord_matches_characters_ignoring_case() {
+        let chord = chord(vec![Modifier::Ctrl], ChordKey::Character("k".to_owned()));
+
+        assert!(chord.matches(&Key::Character("k".into()), Modifiers::CTRL));
+        assert!(chord.matches(&Key::Character("K".into()), Modifiers::CTRL));
+        assert!(!chord.matches(&Key::Character("j".into()), Modifiers::CTRL));
+        assert!(!chord.matches(&Key::Named(Named::F12), Modifiers::CTRL));
+    }
+
+    #[test]
+    fn chord_key_parses_characters_and_names() {
+        assert_eq!(
+            ChordKey::try_from("k".to_owned()),
+            Ok(ChordKey::Character("k".to_owned()))
+        );
+        assert_eq!(
+            ChordKey::try_from("escape".to_owned()),
+            Ok(ChordKey::Named(Named::Escape))
+        );
+        assert_eq!(
+            ChordKey::try_from("Bogus".to_owned()),
+            Err("Unknown key Bogus".to_owned())
+        );
+    }
+
+    #[test]
+    fn chord_key_round_trips_through_its_name() {
+        for (name, named) in ChordKey::NAMED {
+            assert_eq!(String::from(ChordKey::Named(named)), name);
+            assert_eq!(
+                ChordKey::try_from(name.to_owned()),
+                Ok(ChordKey::Named(named))
+            );
+        }
+    }
+
+    #[test]
+    fn address_parses_ips_and_names() {
+        assert_eq!(
+            Address::parse(" 192.168.1.10 "),
+            Some(Address::Ip(IpAddr::from([192, 168, 1, 10])))
+        );
+        assert_eq!(Address::parse("[fe80::1]"), Address::parse("fe80::1"));
+        assert!(matches!(
+            Address::parse("[fe80::1]"),
+            Some(Address::Ip(IpAddr::V6(_)))
+        ));
+        assert_eq!(
+            Address::parse("pve1.example.com"),
+            Some(Address::Dns("pve1.example.com".to_owned()))
+        );
+    }
+
+    #[test]
+    fn address_rejects_invalid_names() {
+        for address in ["", "pve1..example.com", "-pve1", "pve1:8006", "pve 1"] {
+            assert_eq!(Address::parse(address), None, "{address}");
+        }
+    }
+
+    #[test]
+    fn address_brackets_ipv6_when_displayed() {
+        let addresses = ["[fe80::1]", "10.0.0.1", "pve1"].map(Address::parse);
+
+        assert_eq!(
+            addresses.map(|address| address.map(|address| address.to_string())),
+            [
+                Some("[fe80::1]".to_owned()),
+                Some("10.0.0.1".to_owned()),
+                Some("pve1".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn shift_index_follows_removed_entries() {
+        assert_eq!(shift_index(Some(1), 1), None);
+        assert_eq!(shift_index(Some(2), 1), Some(1));
+        assert_eq!(shift_index(Some(0), 1), Some(0));
+        assert_eq!(shift_index(None, 1), None);
+    }
+
+    fn node(name: &str, ip: Option<&str>) -> ClusterNode {
+        ClusterNode {
+            name: name.to_owned(),
+            ip: ip.map(str::to_owned),
+        }
+    }
+
+    fn host(address: &str, port: u16) -> Option<Host> {
+        Address::parse(address).map(|address| Host::new(address, port))
+    }
+
+    #[test]
+    fn update_hosts_adds_new_nodes_on_the_port_of_the_first_host() {
+        let mut cluster = Cluster {
+            hosts: host("10.0.0.1", 443).into_iter().collect(),
+            ..Cluster::default()
+        };
+        let nodes = [
+            node("pve1", Some("10.0.0.1")),
+            node("pve2", Some("10.0.0.2")),
+        ];
+
+        assert!(cluster.update_hosts(&nodes));
+        assert_eq!(
+            cluster.hosts.into_iter().map(Some).collect::<Vec<_>>(),
+            [host("10.0.0.1", 443), host("10.0.0.2", 443)]
+        );
+    }
+
+    #[test]
+    fn update_hosts_matches_hosts_given_by_name() {
+        let mut cluster = Cluster {
+            hosts: host("PVE1.example.com", 8006).into_iter().collect(),
+            ..Cluster::default()
+        };
+
+        assert!(!cluster.update_hosts(&[node("pve1", Some("10.0.0.1")), node("pve3", None)]));
+        assert_eq!(cluster.hosts.len(), 1);
+    }
+
+    #[test]
+    fn update_hosts_never_removes_hosts() {
+        let mut cluster = Cluster {
+            hosts: ["10.0.0.1", "10.0.0.9"]
+                .into_iter()
+                .filter_map(|address| host(address, 8006))
+                .collect(),
+            ..Cluster::default()
+        };
+
+        assert!(!cluster.update_hosts(&[node("pve1", Some("10.0.0.1"))]));
+        assert_eq!(cluster.hosts.len(), 2);
+    }
+}
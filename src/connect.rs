@@ -1,145 +1,904 @@
 use crate::{
-    config::{Config, User},
+    config::{
+        AuthMethod, Config, GuestGrouping, GuestRef, GuestSort, StateFilter, ViewerProfile,
+    },
     include_svg,
-    proxmox::{Auth, Guest, GuestKind, SpiceConfig, Ticket},
+    monitors::{self, Monitor},
+    proxmox::{
+        self, Auth, Client, ClusterNode, ConnectionConfig, Guest, GuestKind, GuestStatus, PowerAction, RunState,
+        TaskState, TaskStatus, Ticket,
+    },
     styles::ui_box,
+    terminal,
+    usb::{self, UsbDevice},
+    viewer,
 };
 use iced::{
-    alignment::Horizontal, time::{every, minutes}, widget::{button, center, column, container, scrollable, stack, svg, text}, Center, Element, Fill, Shrink,
+    alignment::Horizontal, event::{self, listen_with, Status}, keyboard, time::{every, minutes, seconds}, widget::{button, center, column, container, operation, pick_list, row, scrollable, stack, svg, text, text_input, Column, Space}, Border, Center, Element, Fill, Padding,
+    Shrink,
     Subscription,
     Task,
     Theme,
 };
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::{self, Display},
+    iter,
+};
 
 include_svg!(SETTINGS, "lucide/settings.svg");
+include_svg!(COLLAPSED, "lucide/chevron-right.svg");
+include_svg!(EXPANDED, "lucide/chevron-down.svg");
+include_svg!(STAR, "lucide/star.svg");
+
+/// Recently connected guests shown above the guest list.
+const RECENT_SHOWN: usize = 5;
 
 #[derive(Debug)]
 pub struct State {
     auth: Auth,
     guests: Option<Vec<Guest>>,
+    guests_error: Option<String>,
+    refreshing: bool,
+    search: String,
+    /// Groups of the current grouping whose guests are hidden.
+    collapsed: HashSet<String>,
     cluster: usize,
     user: usize,
-    modal: Option<User>,
+    modal: Option<settings_modal::State>,
+    /// Guest whose power actions are shown.
+    actions: Option<u32>,
+    tasks: HashMap<u32, GuestTask>,
+    connections: HashMap<u32, Connection>,
+    /// Viewer profile chosen for this session, replacing those assigned to guests.
+    viewer_profile: ProfileChoice,
+    monitors: Vec<Monitor>,
+    usb_modal: Option<usb_modal::State>,
+    /// USB devices picked to be redirected to guests that are being connected to.
+    usb_devices: HashMap<u32, Vec<UsbDevice>>,
+    /// Outcome of discovering the nodes of the cluster, once it has been asked for.
+    discovery: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ProfileChoice {
+    /// Profile assigned to each guest in the config.
+    #[default]
+    Assigned,
+    Named(String),
+}
+
+impl Display for ProfileChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Assigned => write!(f, "Assigned profile"),
+            Self::Named(name) => name.fmt(f),
+        }
+    }
+}
+
+/// Power action that is running, has failed, or finished with warnings on a guest.
+#[derive(Debug)]
+struct GuestTask {
+    action: PowerAction,
+    node: String,
+    upid: Option<String>,
+    polling: bool,
+    /// Polls in a row that couldn't reach the cluster, which are retried up to a limit.
+    failed_polls: u32,
+    error: Option<String>,
+    /// Warnings the task finished with, such as `WARNINGS: 2`.
+    warnings: Option<String>,
+}
+
+impl GuestTask {
+    /// Polls that may fail in a row before the task is given up on.
+    const MAX_FAILED_POLLS: u32 = 5;
+
+    const fn running(&self) -> bool {
+        self.error.is_none() && self.warnings.is_none()
+    }
+}
+
+/// Connection attempt to a guest, which may have to be started first.
+#[derive(Debug)]
+enum Connection {
+    Checking,
+    /// The guest is stopped and starting it has to be confirmed.
+    Stopped,
+    /// Waiting for the guest to come up after starting it.
+    Starting {
+        attempts: u32,
+        polling: bool,
+    },
+    Requesting,
+    Failed(String),
 }
 
 #[derive(Clone, Debug)]
 pub enum Message {
     Ticket(Ticket),
-    GetGuests(Vec<Guest>),
-    SpiceConfig(SpiceConfig),
+    RenewTicket,
+    RefreshGuests,
+    Search(String),
+    TypeToSearch(String),
+    Filter(Filter),
+    ToggleGroup(String),
+    ToggleFavorite(u32),
+    ViewerProfile(ProfileChoice),
+    Monitors(Vec<Monitor>),
+    ToggleMonitor(String),
+    GetGuests(Result<Vec<Guest>, proxmox::Error>),
+    DiscoverNodes,
+    Nodes(Result<Vec<ClusterNode>, proxmox::Error>),
+    ConnectionConfig(u32, Result<ConnectionConfig, proxmox::Error>),
+    /// The terminal of a container was started or failed to be, or its relay failed afterwards.
+    Terminal(u32, Result<(), String>),
     ConnectHost(u32),
+    GuestStatus(u32, Result<GuestStatus, proxmox::Error>),
+    StartAndConnect(u32),
+    CancelConnection(u32),
+    PollConnections,
+    ToggleActions(u32),
+    Power(u32, PowerAction),
+    PowerStarted(u32, Result<String, proxmox::Error>),
+    PollTasks,
+    TaskStatus(u32, Result<TaskStatus, proxmox::Error>),
+    Lock,
     Logout,
     Settings,
     Modal(settings_modal::Message),
+    UsbModal(usb_modal::Message),
+}
+
+#[derive(Clone, Debug)]
+pub enum Filter {
+    ToggleNode(String),
+    ToggleKind(GuestKind),
+    State(StateFilter),
+    Sort(GuestSort),
+    Grouping(GuestGrouping),
 }
 
 #[derive(Debug)]
 pub enum Action {
+    Lock {
+        cluster: usize,
+        user: usize,
+    },
     Logout(usize),
+    /// Deletes the user, which also logs them out.
+    DeleteUser(usize),
     Run(Task<Message>),
+    SaveConfig,
     None,
 }
 
 impl State {
-    pub fn new(auth: Auth, cluster: usize, user: usize) -> (Self, Task<Message>) {
-        (
-            Self {
-                auth,
-                guests: None,
-                cluster,
-                user,
-                modal: None,
-            },
-            // TODO: Replace with api call
-            Task::done(Message::GetGuests(
-                (0..6)
-                    .map(|i| Guest {
-                        name: format!("Guest{i}"),
-                        vmid: 100 + i,
-                        node: "N1".to_owned(),
-                        kind: GuestKind::Qemu,
-                    })
-                    .collect(),
-            )),
-        )
+    const SEARCH_ID: &str = "guest_search";
+
+    pub fn new(auth: Auth, cluster: usize, user: usize, config: &Config) -> (Self, Task<Message>) {
+        let state = Self {
+            auth,
+            guests: None,
+            guests_error: None,
+            refreshing: true,
+            search: String::new(),
+            collapsed: HashSet::new(),
+            cluster,
+            user,
+            modal: None,
+            actions: None,
+            tasks: HashMap::new(),
+            connections: HashMap::new(),
+            viewer_profile: ProfileChoice::Assigned,
+            monitors: Vec::new(),
+            usb_modal: None,
+            usb_devices: HashMap::new(),
+            discovery: None,
+        };
+        let mut task = Task::batch([
+            state.request_guests(config),
+            Task::perform(async { monitors::detect() }, Message::Monitors),
+        ]);
+
+        // Membership is checked quietly, so the hosts are current next time without anyone noticing
+        if !config.clusters[cluster].fixed_hosts {
+            task = Task::batch([
+                task,
+                Task::perform(state.client(config).nodes(), Message::Nodes),
+            ]);
+        }
+
+        (state, task)
     }
 
-    pub fn subscription(&self) -> Subscription<Message> {
-        match &self.auth {
+    /// `covered` is whether something like the lock covers the screen, whose password could end up
+    /// in the search box otherwise.
+    pub fn subscription(&self, covered: bool) -> Subscription<Message> {
+        let ticket_sub = match &self.auth {
             Auth::ApiToken(_) => Subscription::none(),
             Auth::Ticket(_) => {
                 // Tickets have a lifetime of 2 hours, so they have to be renewed before then
-                every(minutes(110)).map(|_| {
-                    // TODO: add api request
-                    Message::Ticket(Ticket {
-                        ticket: String::new(),
-                        csrf: String::new(),
-                    })
-                })
+                every(minutes(110)).map(|_| Message::RenewTicket)
             }
-        }
+        };
+
+        let tasks_sub = if self
+            .tasks
+            .values()
+            .any(|task| task.upid.is_some() && task.error.is_none())
+        {
+            every(seconds(1)).map(|_| Message::PollTasks)
+        } else {
+            Subscription::none()
+        };
+
+        let connections_sub = if self
+            .connections
+            .values()
+            .any(|connection| matches!(connection, Connection::Starting { .. }))
+        {
+            every(seconds(1)).map(|_| Message::PollConnections)
+        } else {
+            Subscription::none()
+        };
+
+        // Keeps statuses current, so stopped guests aren't mistaken for running ones
+        let guests_sub = every(seconds(10)).map(|_| Message::RefreshGuests);
+
+        // Typing anywhere that doesn't take text goes into the search box
+        let search_sub = if covered {
+            Subscription::none()
+        } else {
+            listen_with(|event, status, _id| {
+                if status == Status::Captured {
+                    return None;
+                }
+
+                let event::Event::Keyboard(keyboard::Event::KeyPressed {
+                    text: Some(text),
+                    modifiers,
+                    ..
+                }) = event
+                else {
+                    return None;
+                };
+
+                if modifiers.control()
+                    || modifiers.alt()
+                    || modifiers.logo()
+                    || text.chars().any(char::is_control)
+                {
+                    return None;
+                }
+
+                Some(Message::TypeToSearch(text.to_string()))
+            })
+        };
+
+        Subscription::batch([
+            ticket_sub,
+            tasks_sub,
+            connections_sub,
+            guests_sub,
+            search_sub,
+        ])
     }
 
-    pub fn update(&mut self, message: Message, config: &Config) -> Action {
+    /// `admin` is whether admin mode is unlocked, which changing what every user shares needs.
+    pub fn update(&mut self, message: Message, config: &mut Config, admin: bool) -> Action {
         match message {
             Message::Ticket(ticket) => {
                 self.auth = Auth::Ticket(ticket);
 
                 Action::None
             }
-            Message::GetGuests(guests) => {
-                self.guests = Some(guests);
+            Message::RenewTicket => self.renew_ticket(config),
+            Message::RefreshGuests => {
+                if self.refreshing {
+                    return Action::None;
+                }
 
+                self.refreshing = true;
+                Action::Run(self.request_guests(config))
+            }
+            Message::Search(search) => {
+                self.search = search;
                 Action::None
             }
-            Message::SpiceConfig(_spice_config) => {
-                // TODO: start remote viewer with config
+            // Modals have inputs of their own, such as the API token of the user
+            Message::TypeToSearch(_) if self.modal.is_some() || self.usb_modal.is_some() => {
                 Action::None
             }
-            Message::ConnectHost(_vmid) => {
-                // TODO: Replace with attempt connection
-                Action::Run(Task::done(Message::SpiceConfig(SpiceConfig {
-                    host: String::new(),
-                    password: String::new(),
-                    proxy: String::new(),
-                    tls_port: 0,
-                    conn_type: String::new(),
-                })))
+            Message::TypeToSearch(text) => {
+                self.search.push_str(&text);
+                Action::Run(
+                    operation::focus(Self::SEARCH_ID)
+                        .chain(operation::move_cursor_to_end(Self::SEARCH_ID)),
+                )
             }
+            Message::Filter(filter) => {
+                self.filter(filter, config);
+                Action::SaveConfig
+            }
+            Message::ToggleFavorite(vmid) => {
+                let cluster = config.clusters[self.cluster].name.clone();
+                toggle(
+                    &mut config.users[self.user].favorites,
+                    GuestRef { cluster, vmid },
+                );
+                Action::SaveConfig
+            }
+            Message::ViewerProfile(choice) => {
+                self.viewer_profile = choice;
+                Action::None
+            }
+            Message::Monitors(monitors) => {
+                self.monitors = monitors;
+                Action::None
+            }
+            Message::ToggleMonitor(monitor) => {
+                toggle(&mut config.users[self.user].monitors, monitor);
+                Action::SaveConfig
+            }
+            Message::ToggleGroup(group) => {
+                if !self.collapsed.remove(&group) {
+                    self.collapsed.insert(group);
+                }
+                Action::None
+            }
+            Message::GetGuests(result) => self.get_guests(result, config),
+            // Hosts are shared by every user, so only admins may go looking for more
+            Message::DiscoverNodes if !admin => Action::None,
+            Message::DiscoverNodes => {
+                self.discovery = Some("Discovering nodes...".to_owned());
+                Action::Run(Task::perform(self.client(config).nodes(), Message::Nodes))
+            }
+            Message::Nodes(result) => self.nodes(result, config),
+            Message::ConnectionConfig(vmid, result) => self.launch_viewer(vmid, result, config),
+            Message::Terminal(vmid, result) => self.launched(vmid, result, config),
+            Message::ConnectHost(vmid) => self.connect(vmid, config),
+            Message::GuestStatus(vmid, result) => self.guest_status(vmid, result, config),
+            Message::StartAndConnect(vmid) => self.start_and_connect(vmid, config),
+            Message::CancelConnection(vmid) => {
+                self.connections.remove(&vmid);
+                Action::None
+            }
+            Message::PollConnections => self.poll_connections(config),
+            Message::ToggleActions(vmid) => {
+                self.actions = (self.actions != Some(vmid)).then_some(vmid);
+                Action::None
+            }
+            Message::Power(vmid, action) => self.power(vmid, action, config),
+            Message::PowerStarted(vmid, result) => {
+                self.power_started(vmid, result);
+                Action::None
+            }
+            Message::PollTasks => self.poll_tasks(config),
+            Message::TaskStatus(vmid, result) => {
+                self.task_status(vmid, result);
+                Action::None
+            }
+            Message::Lock => Action::Lock {
+                cluster: self.cluster,
+                user: self.user,
+            },
             Message::Logout => Action::Logout(self.user),
-            Message::Settings => {
-                self.modal = Some(config.users[self.user].clone());
+            Message::Settings => self.open_settings(config),
+            Message::UsbModal(message) => self.update_usb_modal(message, config),
+            Message::Modal(message) => self.update_modal(message, config),
+        }
+    }
+
+    fn open_settings(&mut self, config: &Config) -> Action {
+        let (state, task) = settings_modal::State::new(self.user, config);
+        self.modal = Some(state);
+
+        Action::Run(task.map(Message::Modal))
+    }
+
+    /// Requests a new ticket before the current one expires, so the session stays alive even while
+    /// the screen is locked.
+    fn renew_ticket(&self, config: &Config) -> Action {
+        let (Auth::Ticket(ticket), Some(username)) =
+            (&self.auth, config.users[self.user].auth_method.login_name())
+        else {
+            return Action::None;
+        };
+
+        // A valid ticket is accepted as the password, and the current ticket is kept if renewing
+        // fails, as it's still valid for a while
+        Action::Run(
+            Task::perform(
+                Client::new(&config.clusters[self.cluster]).login(username, ticket.ticket.clone()),
+                Result::ok,
+            )
+            .and_then(|ticket| Task::done(Message::Ticket(ticket))),
+        )
+    }
+
+    /// Updates the hosts of the cluster to its members, only reporting the outcome if it was asked
+    /// for.
+    fn nodes(
+        &mut self,
+        result: Result<Vec<ClusterNode>, proxmox::Error>,
+        config: &mut Config,
+    ) -> Action {
+        let (status, changed) = match result {
+            Ok(nodes) => {
+                let changed = config.clusters[self.cluster].update_hosts(&nodes);
+                let names: Vec<&str> = nodes.iter().map(|node| node.name.as_str()).collect();
+                let status = format!("Found {}", names.join(", "));
+                (status, changed)
+            }
+            Err(err) => (err.to_string(), false),
+        };
+
+        if let Some(discovery) = &mut self.discovery {
+            *discovery = status;
+        }
+
+        if changed {
+            Action::SaveConfig
+        } else {
+            Action::None
+        }
+    }
+
+    fn update_modal(&mut self, message: settings_modal::Message, config: &mut Config) -> Action {
+        let Some(state) = &mut self.modal else {
+            return Action::None;
+        };
+
+        match state.update(message) {
+            settings_modal::Action::Save {
+                display_name,
+                auth_method,
+                default_cluster,
+            } => {
+                self.modal = None;
+                let user = &mut config.users[self.user];
+                user.display_name = display_name;
+                user.auth_method = auth_method;
+                config.default_cluster = default_cluster;
+                Action::SaveConfig
+            }
+            settings_modal::Action::Delete => Action::DeleteUser(self.user),
+            settings_modal::Action::Close => {
+                self.modal = None;
+                Action::None
+            }
+            settings_modal::Action::None => Action::None,
+        }
+    }
+
+    fn update_usb_modal(&mut self, message: usb_modal::Message, config: &Config) -> Action {
+        let Some(state) = &mut self.usb_modal else {
+            return Action::None;
+        };
 
+        match state.update(message) {
+            usb_modal::Action::Connect(vmid, devices) => {
+                self.usb_modal = None;
+                self.usb_devices.insert(vmid, devices);
+                self.check_status(vmid, config)
+            }
+            usb_modal::Action::Close => {
+                self.usb_modal = None;
+                Action::None
+            }
+            usb_modal::Action::None => Action::None,
+        }
+    }
+
+    fn filter(&mut self, filter: Filter, config: &mut Config) {
+        let list = &mut config.users[self.user].guest_list;
+
+        match filter {
+            Filter::ToggleNode(node) => toggle(&mut list.nodes, node),
+            Filter::ToggleKind(kind) => toggle(&mut list.kinds, kind),
+            Filter::State(state) => list.state = state,
+            Filter::Sort(sort) => list.sort = sort,
+            Filter::Grouping(grouping) => {
+                // Group names of different groupings are unrelated
+                self.collapsed.clear();
+                list.grouping = grouping;
+            }
+        }
+    }
+
+    fn connect(&mut self, vmid: u32, config: &Config) -> Action {
+        if self.connections.get(&vmid).is_some_and(|connection| {
+            !matches!(connection, Connection::Failed(_) | Connection::Stopped)
+        }) {
+            return Action::None;
+        }
+
+        self.usb_devices.remove(&vmid);
+
+        // Only VMs are shown with a viewer that could redirect devices
+        if config.usb.picker
+            && self
+                .guest(vmid)
+                .is_some_and(|guest| guest.kind == GuestKind::Qemu)
+        {
+            let devices: Vec<UsbDevice> = usb::devices()
+                .into_iter()
+                .filter(|device| config.usb.allows(device))
+                .collect();
+
+            if !devices.is_empty() {
+                self.usb_modal = Some(usb_modal::State::new(vmid, devices));
+                return Action::None;
+            }
+        }
+
+        self.check_status(vmid, config)
+    }
+
+    fn check_status(&mut self, vmid: u32, config: &Config) -> Action {
+        self.connections.insert(vmid, Connection::Checking);
+        self.request_status(vmid, config)
+            .map_or(Action::None, Action::Run)
+    }
+
+    fn poll_connections(&mut self, config: &Config) -> Action {
+        let vmids: Vec<u32> = self
+            .connections
+            .iter_mut()
+            .filter_map(|(&vmid, connection)| match connection {
+                Connection::Starting { polling, .. } if !*polling => {
+                    *polling = true;
+                    Some(vmid)
+                }
+                _ => None,
+            })
+            .collect();
+
+        Action::Run(Task::batch(
+            vmids
+                .into_iter()
+                .filter_map(|vmid| self.request_status(vmid, config)),
+        ))
+    }
+
+    fn launch_viewer(
+        &mut self,
+        vmid: u32,
+        result: Result<ConnectionConfig, proxmox::Error>,
+        config: &mut Config,
+    ) -> Action {
+        let result = result
+            .map_err(|err| err.to_string())
+            .and_then(|connection_config| {
+                // The admin's overrides win over the user's choice
+                let mut options =
+                    usb::vv_options(&config.usb, self.usb_devices.remove(&vmid).as_deref());
+                options.extend(config.vv_overrides.clone());
+
+                viewer::launch(
+                    &connection_config,
+                    vmid,
+                    &self.viewer_profile(vmid, config),
+                    &options,
+                )
+                .map_err(|err| format!("Failed to start viewer: {err}"))
+            });
+
+        self.launched(vmid, result, config)
+    }
+
+    /// Viewer profile for the guest, spanning the monitors chosen by the user or the profile.
+    fn viewer_profile(&self, vmid: u32, config: &Config) -> ViewerProfile {
+        let name = match &self.viewer_profile {
+            ProfileChoice::Assigned => config.viewer_profile_name(self.cluster, self.user, vmid),
+            ProfileChoice::Named(name) => Some(name.as_str()),
+        };
+        let mut profile = config.viewer_profile(name);
+
+        let user = &config.users[self.user];
+        let selected = if user.monitors.is_empty() {
+            &profile.monitors
+        } else {
+            &user.monitors
+        };
+
+        if let Some(mapping) = monitors::mapping(&self.monitors, selected) {
+            profile.monitor_mapping = Some(mapping);
+            profile.args.push("--full-screen".to_owned());
+        }
+
+        profile
+    }
+
+    /// Finishes connecting to the guest once its console was started, or failed to.
+    fn launched(&mut self, vmid: u32, result: Result<(), String>, config: &mut Config) -> Action {
+        match result {
+            Ok(()) => {
+                self.connections.remove(&vmid);
+
+                let cluster = config.clusters[self.cluster].name.clone();
+                config.users[self.user].record_connection(GuestRef { cluster, vmid });
+
+                Action::SaveConfig
+            }
+            Err(err) => {
+                self.connections.insert(vmid, Connection::Failed(err));
                 Action::None
             }
-            Message::Modal(message) => {
-                if let Some(user) = &mut self.modal {
-                    match settings_modal::update(user, message) {
-                        settings_modal::Action::Close => self.modal = None,
+        }
+    }
+
+    fn guest(&self, vmid: u32) -> Option<&Guest> {
+        self.guests
+            .iter()
+            .flatten()
+            .find(|guest| guest.vmid == vmid)
+    }
+
+    fn get_guests(
+        &mut self,
+        result: Result<Vec<Guest>, proxmox::Error>,
+        config: &mut Config,
+    ) -> Action {
+        self.refreshing = false;
+
+        match result {
+            Ok(guests) => {
+                let vmids: Vec<u32> = guests.iter().map(|guest| guest.vmid).collect();
+                self.guests = Some(guests);
+                self.guests_error = None;
+
+                // Guests that were deleted or migrated away can't be connected to anymore
+                if config.users[self.user]
+                    .forget_missing(&config.clusters[self.cluster].name, &vmids)
+                {
+                    return Action::SaveConfig;
+                }
+            }
+            Err(err) => self.guests_error = Some(err.to_string()),
+        }
+
+        Action::None
+    }
+
+    fn request_guests(&self, config: &Config) -> Task<Message> {
+        Task::perform(self.client(config).guests(), Message::GetGuests)
+    }
+
+    fn request_status(&self, vmid: u32, config: &Config) -> Option<Task<Message>> {
+        let guest = self.guest(vmid)?;
+
+        Some(Task::perform(
+            self.client(config).guest_status(guest.clone()),
+            move |result| Message::GuestStatus(vmid, result),
+        ))
+    }
+
+    fn guest_status(
+        &mut self,
+        vmid: u32,
+        result: Result<GuestStatus, proxmox::Error>,
+        config: &Config,
+    ) -> Action {
+        /// Attempts made to reach the display of a starting guest, one per second.
+        const MAX_ATTEMPTS: u32 = 120;
+
+        let Some(guest) = self.guest(vmid).cloned() else {
+            return Action::None;
+        };
+        let Some(connection) = self.connections.get_mut(&vmid) else {
+            return Action::None;
+        };
+
+        let status = match result {
+            Ok(status) => status,
+            Err(err) => {
+                *connection = Connection::Failed(err.to_string());
+                return Action::None;
+            }
+        };
+
+        match connection {
+            _ if status.ready() => {
+                *connection = Connection::Requesting;
+
+                let client = self.client(config);
+                // Containers are reached through a terminal instead of a display
+                Action::Run(match guest.kind {
+                    GuestKind::Qemu => {
+                        Task::perform(client.connection_config(guest), move |result| {
+                            Message::ConnectionConfig(vmid, result)
+                        })
                     }
+                    GuestKind::Lxc => Task::run(
+                        terminal::open(client, guest, config.terminal_command.clone()),
+                        move |result| Message::Terminal(vmid, result),
+                    ),
+                })
+            }
+            Connection::Checking if status.status == RunState::Stopped => {
+                if config.auto_start_guests {
+                    self.start_and_connect(vmid, config)
+                } else {
+                    *connection = Connection::Stopped;
+                    Action::None
+                }
+            }
+            Connection::Checking => {
+                *connection = Connection::Failed(format!(
+                    "Guest is {}",
+                    status.qmpstatus.unwrap_or(status.status)
+                ));
+                Action::None
+            }
+            Connection::Starting { attempts, polling } => {
+                *polling = false;
+                *attempts += 1;
+
+                if let Some(error) = self.tasks.get(&vmid).and_then(|task| task.error.clone()) {
+                    *connection = Connection::Failed(error);
+                } else if *attempts >= MAX_ATTEMPTS {
+                    *connection = Connection::Failed("Timed out waiting for guest".to_owned());
                 }
 
                 Action::None
             }
+            Connection::Stopped | Connection::Requesting | Connection::Failed(_) => Action::None,
         }
     }
 
-    pub fn view<'a>(&'a self, config: &'a Config) -> Element<'a, Message> {
+    fn start_and_connect(&mut self, vmid: u32, config: &Config) -> Action {
+        self.connections.insert(
+            vmid,
+            Connection::Starting {
+                attempts: 0,
+                polling: false,
+            },
+        );
+
+        // Starting goes through the power actions so the task shows up on the guest card
+        self.power(vmid, PowerAction::Start, config)
+    }
+
+    fn power(&mut self, vmid: u32, action: PowerAction, config: &Config) -> Action {
+        let Some(guest) = self.guest(vmid).cloned() else {
+            return Action::None;
+        };
+
+        if self.tasks.get(&vmid).is_some_and(GuestTask::running) {
+            return Action::None;
+        }
+
+        self.tasks.insert(
+            vmid,
+            GuestTask {
+                action,
+                node: guest.node.clone(),
+                upid: None,
+                polling: false,
+                failed_polls: 0,
+                error: None,
+                warnings: None,
+            },
+        );
+
+        Action::Run(Task::perform(
+            self.client(config).power(guest, action),
+            move |result| Message::PowerStarted(vmid, result),
+        ))
+    }
+
+    fn power_started(&mut self, vmid: u32, result: Result<String, proxmox::Error>) {
+        if let Some(task) = self.tasks.get_mut(&vmid) {
+            match result {
+                Ok(upid) => task.upid = Some(upid),
+                Err(err) => task.error = Some(err.to_string()),
+            }
+        }
+    }
+
+    fn poll_tasks(&mut self, config: &Config) -> Action {
+        let client = self.client(config);
+
+        Action::Run(Task::batch(self.tasks.iter_mut().filter_map(
+            |(&vmid, task)| {
+                if task.polling || !task.running() {
+                    return None;
+                }
+
+                let upid = task.upid.clone()?;
+                task.polling = true;
+
+                Some(Task::perform(
+                    client.clone().task_status(task.node.clone(), upid),
+                    move |result| Message::TaskStatus(vmid, result),
+                ))
+            },
+        )))
+    }
+
+    fn task_status(&mut self, vmid: u32, result: Result<TaskStatus, proxmox::Error>) {
+        let Some(task) = self.tasks.get_mut(&vmid) else {
+            return;
+        };
+
+        task.polling = false;
+        if result.is_ok() {
+            task.failed_polls = 0;
+        }
+
+        match result {
+            Ok(TaskStatus {
+                status: TaskState::Running,
+                ..
+            }) => {}
+            Ok(TaskStatus {
+                status: TaskState::Stopped,
+                exitstatus,
+            }) => match exitstatus {
+                // The task did what it was asked to, but something along the way is worth telling
+                Some(status) if status.starts_with("WARNINGS:") => task.warnings = Some(status),
+                Some(status) if status != "OK" => task.error = Some(status),
+                _ => {
+                    self.tasks.remove(&vmid);
+                }
+            },
+            // The task keeps running when the cluster can't be reached for a moment
+            Err(proxmox::Error::Unreachable(_))
+                if task.failed_polls + 1 < GuestTask::MAX_FAILED_POLLS =>
+            {
+                task.failed_polls += 1;
+            }
+            Err(err) => task.error = Some(err.to_string()),
+        }
+    }
+
+    fn client(&self, config: &Config) -> Client {
+        Client::new(&config.clusters[self.cluster]).with_auth(self.auth.clone())
+    }
+
+    pub fn view<'a>(&'a self, config: &'a Config, admin: bool) -> Element<'a, Message> {
         let Some(guests) = &self.guests else {
-            return center("Getting guests...").into();
+            return center(
+                self.guests_error
+                    .as_ref()
+                    .map_or_else(|| text("Getting guests..."), |error| text(error.as_str())),
+            )
+            .into();
         };
 
         let hosts = container(
             scrollable(
-                column(guests.iter().map(view_guest))
-                    .align_x(Center)
-                    .spacing(4),
+                column![
+                    self.view_shortcuts(guests, config),
+                    self.view_guests(guests, config).spacing(4),
+                ]
+                .align_x(Center)
+                .spacing(12),
             )
             .height(240),
         )
-        .padding([50, 20]);
+        .padding([20, 20]);
+
+        // The guests from before are still shown, so they can be connected to meanwhile
+        let guests_error = self.guests_error.as_ref().map(|error| {
+            text(format!("Failed to refresh the guests: {error}"))
+                .size(12.5)
+                .style(|theme: &Theme| text::Style {
+                    color: Some(theme.palette().danger),
+                })
+        });
 
         let logout_button = button("Logout").on_press(Message::Logout);
+        // Unlocking checks the password again, which API token users don't have
+        let lock_button = matches!(
+            config.users[self.user].auth_method,
+            AuthMethod::Password { .. }
+        )
+        .then(|| button("Lock").on_press(Message::Lock));
         let settings_button = button(svg(SETTINGS.clone()).style(|theme: &Theme, _| svg::Style {
             color: Some(theme.extended_palette().primary.base.text),
         }))
@@ -151,10 +910,24 @@ impl State {
                 text(config.users[self.user].to_string())
                     .size(25)
                     .width(Fill),
+                self.view_filters(guests, config),
+                guests_error,
                 hosts,
-                logout_button,
+                self.view_viewer(config),
+                row![lock_button, logout_button].spacing(10),
                 container(Option::<Element<Message>>::None).height(Fill),
-                container(settings_button).width(Fill)
+                row![
+                    settings_button,
+                    admin.then(|| {
+                        button("Discover nodes")
+                            .style(button::secondary)
+                            .on_press(Message::DiscoverNodes)
+                    }),
+                    self.discovery.as_deref().map(text),
+                ]
+                .spacing(10)
+                .align_y(Center)
+                .width(Fill)
             ]
             .align_x(Horizontal::Center),
         )
@@ -166,55 +939,828 @@ impl State {
             container(menu).padding(20),
             self.modal
                 .as_ref()
-                .map(|user| settings_modal::view(user).map(Message::Modal))
+                .map(|state| state.view(config).map(Message::Modal)),
+            self.usb_modal
+                .as_ref()
+                .map(|state| state.view().map(Message::UsbModal))
         ]
         .width(Fill)
         .into()
     }
+
+    /// Guests matching the search and filters of the user, in their chosen order.
+    fn visible_guests<'a>(&self, guests: &'a [Guest], config: &Config) -> Vec<&'a Guest> {
+        let user = &config.users[self.user];
+        let list = &user.guest_list;
+        let search = self.search.to_lowercase();
+        let cluster = &config.clusters[self.cluster].name;
+
+        let mut visible: Vec<&Guest> = guests
+            .iter()
+            .filter(|guest| {
+                guest.name.to_lowercase().contains(&search)
+                    || guest.vmid.to_string().contains(&search)
+            })
+            .filter(|guest| list.nodes.is_empty() || list.nodes.contains(&guest.node))
+            .filter(|guest| list.kinds.is_empty() || list.kinds.contains(&guest.kind))
+            .filter(|guest| match list.state {
+                StateFilter::All => true,
+                StateFilter::Running => guest.status == RunState::Running,
+                StateFilter::Stopped => guest.status != RunState::Running,
+            })
+            .collect();
+
+        let last_used = |guest: &Guest| {
+            user.recent
+                .iter()
+                .position(|recent| recent.cluster == *cluster && recent.vmid == guest.vmid)
+                .unwrap_or(usize::MAX)
+        };
+        let by_name = |a: &&Guest, b: &&Guest| {
+            a.name
+                .to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then(a.vmid.cmp(&b.vmid))
+        };
+
+        match list.sort {
+            GuestSort::Name => visible.sort_by(by_name),
+            GuestSort::Vmid => visible.sort_by_key(|guest| guest.vmid),
+            GuestSort::LastUsed => visible.sort_by(|a, b| match last_used(a).cmp(&last_used(b)) {
+                Ordering::Equal => by_name(a, b),
+                ordering => ordering,
+            }),
+        }
+
+        visible
+    }
+
+    fn view_guests<'a>(&'a self, guests: &'a [Guest], config: &'a Config) -> Column<'a, Message> {
+        let grouping = config.users[self.user].guest_list.grouping;
+        let visible = self.visible_guests(guests, config);
+
+        let ungrouped_label = match grouping {
+            GuestGrouping::None => {
+                return column(
+                    visible
+                        .into_iter()
+                        .map(|guest| self.view_guest(guest, config)),
+                );
+            }
+            GuestGrouping::Pool => "No pool",
+            GuestGrouping::Tag => "Untagged",
+            // Every guest is on a node
+            GuestGrouping::Node => "",
+        };
+
+        // Guests tagged more than once show up in each of their groups
+        let mut groups: BTreeMap<Option<&str>, Vec<&Guest>> = BTreeMap::new();
+        for guest in visible {
+            let keys = match grouping {
+                GuestGrouping::Pool => vec![guest.pool.as_deref()],
+                GuestGrouping::Tag if guest.tags.is_empty() => vec![None],
+                GuestGrouping::Tag => guest.tags.iter().map(|tag| Some(tag.as_str())).collect(),
+                GuestGrouping::None | GuestGrouping::Node => vec![Some(guest.node.as_str())],
+            };
+
+            for key in keys {
+                groups.entry(key).or_default().push(guest);
+            }
+        }
+
+        // Ungrouped guests go last rather than first
+        let (ungrouped, named): (Vec<_>, Vec<_>) =
+            groups.into_iter().partition(|(group, _)| group.is_none());
+
+        column(named.into_iter().chain(ungrouped).map(|(group, guests)| {
+            let label = group.unwrap_or(ungrouped_label);
+            let collapsed = self.collapsed.contains(label);
+
+            let header = button(
+                row![
+                    svg(if collapsed {
+                        COLLAPSED.clone()
+                    } else {
+                        EXPANDED.clone()
+                    })
+                    .width(16)
+                    .height(16)
+                    .style(|theme: &Theme, _| svg::Style {
+                        color: Some(theme.extended_palette().background.base.text),
+                    }),
+                    text(format!("{label} ({})", guests.len())).size(14),
+                ]
+                .spacing(4)
+                .align_y(Center),
+            )
+            .style(button::text)
+            .width(200)
+            .on_press(Message::ToggleGroup(label.to_owned()));
+
+            let guests = (!collapsed).then(|| {
+                column(
+                    guests
+                        .into_iter()
+                        .map(|guest| self.view_guest(guest, config)),
+                )
+                .spacing(4)
+            });
+
+            column![header, guests].spacing(4).into()
+        }))
+    }
+
+    /// Viewer profile and monitors to choose from, if there's a choice.
+    fn view_viewer<'a>(&'a self, config: &'a Config) -> Column<'a, Message> {
+        let profiles = (!config.viewer_profiles.is_empty()).then(|| self.view_profiles(config));
+
+        let user = &config.users[self.user];
+        let monitors = (self.monitors.len() > 1).then(|| {
+            let chips = self.monitors.iter().map(|monitor| {
+                chip(
+                    monitor.name.clone(),
+                    user.monitors.contains(&monitor.name),
+                    Message::ToggleMonitor(monitor.name.clone()),
+                )
+            });
+
+            row![text("Monitors").size(12.5), row(chips).spacing(4)]
+                .spacing(6)
+                .align_y(Center)
+        });
+
+        column![profiles, monitors]
+            .spacing(6)
+            .padding(Padding::ZERO.bottom(10))
+    }
+
+    fn view_profiles<'a>(&'a self, config: &'a Config) -> Element<'a, Message> {
+        let choices: Vec<ProfileChoice> = iter::once(ProfileChoice::Assigned)
+            .chain(
+                config
+                    .viewer_profiles
+                    .keys()
+                    .cloned()
+                    .map(ProfileChoice::Named),
+            )
+            .collect();
+
+        row![
+            text("Viewer"),
+            pick_list(
+                choices,
+                Some(self.viewer_profile.clone()),
+                Message::ViewerProfile
+            )
+        ]
+        .spacing(10)
+        .align_y(Center)
+        .into()
+    }
+
+    fn view_filters<'a>(&'a self, guests: &'a [Guest], config: &'a Config) -> Element<'a, Message> {
+        let list = &config.users[self.user].guest_list;
+
+        let search = text_input("Search by name or VMID", &self.search)
+            .on_input(Message::Search)
+            .id(Self::SEARCH_ID);
+
+        let nodes: BTreeSet<&str> = guests.iter().map(|guest| guest.node.as_str()).collect();
+        let node_chips = nodes.into_iter().map(|node| {
+            chip(
+                node.to_owned(),
+                list.nodes.iter().any(|n| n == node),
+                Message::Filter(Filter::ToggleNode(node.to_owned())),
+            )
+        });
+        let kind_chips = GuestKind::ALL.into_iter().map(|kind| {
+            chip(
+                kind.to_string(),
+                list.kinds.contains(&kind),
+                Message::Filter(Filter::ToggleKind(kind)),
+            )
+        });
+        let state_chips = StateFilter::ALL.into_iter().map(|state| {
+            chip(
+                state.to_string(),
+                list.state == state,
+                Message::Filter(Filter::State(state)),
+            )
+        });
+
+        let sort = pick_list(GuestSort::ALL, Some(list.sort), |sort| {
+            Message::Filter(Filter::Sort(sort))
+        })
+        .text_size(12.5);
+        let grouping = pick_list(GuestGrouping::ALL, Some(list.grouping), |grouping| {
+            Message::Filter(Filter::Grouping(grouping))
+        })
+        .text_size(12.5);
+
+        column![
+            search,
+            row(node_chips.chain(kind_chips))
+                .spacing(4)
+                .wrap()
+                .vertical_spacing(4),
+            row(state_chips).spacing(4),
+            row![
+                text("Sort by").size(12.5),
+                sort,
+                text("Group by").size(12.5),
+                grouping
+            ]
+            .spacing(6)
+            .align_y(Center),
+        ]
+        .spacing(6)
+        .width(240)
+        .padding([20, 0])
+        .into()
+    }
+
+    /// Favorite and recently connected guests of the user that are still in the cluster.
+    fn view_shortcuts<'a>(
+        &'a self,
+        guests: &'a [Guest],
+        config: &'a Config,
+    ) -> Option<Column<'a, Message>> {
+        let user = &config.users[self.user];
+        let cluster = &config.clusters[self.cluster].name;
+
+        let section = |label: &'a str, refs: &'a [GuestRef], limit: usize| {
+            let cards: Vec<Element<'a, Message>> = refs
+                .iter()
+                .filter(|guest_ref| guest_ref.cluster == *cluster)
+                .filter_map(|guest_ref| guests.iter().find(|guest| guest.vmid == guest_ref.vmid))
+                .take(limit)
+                .map(|guest| self.view_guest(guest, config))
+                .collect();
+
+            (!cards.is_empty())
+                .then(|| column![text(label).size(14), column(cards).spacing(4)].spacing(4))
+        };
+
+        let favorites = section("Favorites", &user.favorites, usize::MAX);
+        let recent = section("Recent", &user.recent, RECENT_SHOWN);
+
+        (favorites.is_some() || recent.is_some())
+            .then(|| column![favorites, recent, text("All guests").size(14)].spacing(12))
+    }
+
+    fn view_guest<'a>(&'a self, guest: &'a Guest, config: &Config) -> Element<'a, Message> {
+        let task = self.tasks.get(&guest.vmid);
+        let busy = task.is_some_and(GuestTask::running);
+        let cluster = &config.clusters[self.cluster].name;
+        let favorite = config.users[self.user]
+            .favorites
+            .iter()
+            .any(|favorite| favorite.cluster == *cluster && favorite.vmid == guest.vmid);
+
+        let connect = button(column![
+            row![status_dot(guest), text(guest.name.clone())]
+                .spacing(6)
+                .align_y(Center),
+            text(format!("{} · {}", guest.kind, guest_details(guest))).size(12.5)
+        ])
+        .width(Fill)
+        .height(60)
+        .padding(10)
+        .on_press(Message::ConnectHost(guest.vmid));
+
+        let toggle_favorite = button(svg(STAR.clone()).style(move |theme: &Theme, _| {
+            let palette = theme.extended_palette();
+            svg::Style {
+                color: Some(if favorite {
+                    palette.warning.base.color
+                } else {
+                    palette.primary.base.text
+                }),
+            }
+        }))
+        .width(30)
+        .height(30)
+        .padding(6)
+        .on_press(Message::ToggleFavorite(guest.vmid));
+
+        let toggle_actions = button(text("...").center())
+            .width(30)
+            .height(30)
+            .on_press(Message::ToggleActions(guest.vmid));
+
+        let actions = (self.actions == Some(guest.vmid)).then(|| {
+            row(PowerAction::ALL.into_iter().map(|action| {
+                button(text(action.to_string()).size(12.5))
+                    .padding([2, 6])
+                    .on_press_maybe((!busy).then_some(Message::Power(guest.vmid, action)))
+                    .into()
+            }))
+            .spacing(4)
+            .wrap()
+            .vertical_spacing(4)
+        });
+
+        let status = task.map(|task| match (&task.error, &task.warnings) {
+            (Some(error), _) => text(format!("{} failed: {error}", task.action))
+                .size(12.5)
+                .style(|theme: &Theme| text::Style {
+                    color: Some(theme.palette().danger),
+                }),
+            (None, Some(warnings)) => {
+                text(format!("{} finished with {warnings}", task.action)).size(12.5)
+            }
+            (None, None) => text(task.action.progress()).size(12.5),
+        });
+
+        let connection = self
+            .connections
+            .get(&guest.vmid)
+            .map(|connection| view_connection(guest.vmid, connection));
+
+        column![
+            row![connect, column![toggle_favorite, toggle_actions]].spacing(2),
+            actions,
+            status,
+            connection
+        ]
+        .spacing(4)
+        .width(200)
+        .into()
+    }
+}
+
+fn chip<'a>(label: String, selected: bool, message: Message) -> Element<'a, Message> {
+    button(text(label).size(12.5))
+        .padding([2, 8])
+        .style(if selected {
+            button::primary
+        } else {
+            button::secondary
+        })
+        .on_press(message)
+        .into()
 }
 
-fn view_guest(guest: &Guest) -> Element<'_, Message> {
-    button(column![
-        text(guest.name.clone()),
-        text(guest.kind.to_string()).size(12.5)
-    ])
-    .width(170)
-    .height(60)
-    .padding(10)
-    .on_press(Message::ConnectHost(guest.vmid))
-    .into()
+/// Removes `value` if it is present, otherwise adds it.
+fn toggle<T: PartialEq>(values: &mut Vec<T>, value: T) {
+    if let Some(idx) = values.iter().position(|v| *v == value) {
+        values.remove(idx);
+    } else {
+        values.push(value);
+    }
+}
+
+fn status_dot(guest: &Guest) -> Element<'_, Message> {
+    let locked = guest.lock.is_some();
+    let status = guest.status;
+
+    container(Space::new())
+        .width(10)
+        .height(10)
+        .style(move |theme: &Theme| {
+            let palette = theme.extended_palette();
+            let color = match status {
+                _ if locked => palette.warning.base.color,
+                RunState::Running => palette.success.base.color,
+                RunState::Paused | RunState::Suspended | RunState::Prelaunch => {
+                    palette.warning.base.color
+                }
+                RunState::Stopped | RunState::Unknown => palette.background.strong.color,
+            };
+
+            container::Style::default()
+                .background(color)
+                .border(Border::default().rounded(5))
+        })
+        .into()
+}
+
+/// Short description of the guest's state, such as `running 2h 5m, CPU 12%, 1.5/4.0 GiB`.
+fn guest_details(guest: &Guest) -> String {
+    const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+    if let Some(lock) = &guest.lock {
+        return format!("locked for {lock}");
+    }
+
+    if guest.status != RunState::Running {
+        return guest.status.to_string();
+    }
+
+    let hours = guest.uptime / 3600;
+    let minutes = guest.uptime % 3600 / 60;
+
+    #[allow(clippy::cast_precision_loss)]
+    let (mem, maxmem) = (guest.mem as f64 / GIB, guest.maxmem as f64 / GIB);
+
+    format!(
+        "running {hours}h {minutes}m, CPU {:.0}%, {mem:.1}/{maxmem:.1} GiB",
+        guest.cpu * 100.0
+    )
+}
+
+fn view_connection(vmid: u32, connection: &Connection) -> Element<'_, Message> {
+    let message = match connection {
+        Connection::Checking => "Checking guest...",
+        Connection::Stopped => {
+            return column![
+                text("Guest is stopped").size(12.5),
+                row![
+                    button(text("Start and connect").size(12.5))
+                        .padding([2, 6])
+                        .on_press(Message::StartAndConnect(vmid)),
+                    button(text("Cancel").size(12.5))
+                        .padding([2, 6])
+                        .on_press(Message::CancelConnection(vmid)),
+                ]
+                .spacing(4)
+            ]
+            .spacing(4)
+            .into();
+        }
+        Connection::Starting { .. } => "Waiting for guest to start...",
+        Connection::Requesting => "Connecting...",
+        Connection::Failed(error) => {
+            return text(error.as_str())
+                .size(12.5)
+                .style(|theme: &Theme| text::Style {
+                    color: Some(theme.palette().danger),
+                })
+                .into();
+        }
+    };
+
+    text(message).size(12.5).into()
 }
 
 mod settings_modal {
-    use crate::{config::User, modal::modal, styles::ui_box};
-    use iced::widget::svg;
-    use iced::{widget::container, Element};
+    use crate::{
+        config::{AuthMethod, Config},
+        modal::modal,
+        secrets::Secret,
+        styles::ui_box,
+    };
+    use iced::{
+        widget::{button, column, container, operation, pick_list, row, svg, text, text_input},
+        Center, Element, Fill, Task, Theme,
+    };
+    use std::fmt::{self, Display};
+
+    /// Cluster that can be picked as the default one, by its index.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct ClusterChoice<'a> {
+        idx: usize,
+        name: &'a str,
+    }
+
+    impl Display for ClusterChoice<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.name.fmt(f)
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct State {
+        display_name: String,
+        auth_method: AuthMethod,
+        default_cluster: Option<usize>,
+        /// Display names of the other users, which have to differ from this one.
+        taken_names: Vec<String>,
+        display_name_error: Option<&'static str>,
+        auth_error: bool,
+        confirm_delete: bool,
+    }
 
     #[derive(Clone, Debug)]
     pub enum Message {
+        DisplayName(String),
+        Username(String),
+        Realm(String),
+        Password,
+        Api,
+        Token(Secret),
+        DefaultCluster(usize),
+        Delete,
+        CancelDelete,
+        Save,
         Close,
     }
 
     pub enum Action {
+        /// Only the fields edited here are saved, since the rest of the user, such as the recent
+        /// guests, may have changed while the modal was open.
+        Save {
+            display_name: String,
+            auth_method: AuthMethod,
+            default_cluster: Option<usize>,
+        },
+        Delete,
         Close,
+        None,
     }
 
-    #[allow(clippy::needless_pass_by_value)]
-    pub const fn update(_user: &mut User, message: Message) -> Action {
-        match message {
-            Message::Close => Action::Close,
+    impl State {
+        const DISPLAY_NAME_ID: &str = "settings_display_name";
+
+        pub fn new(user: usize, config: &Config) -> (Self, Task<Message>) {
+            let taken_names = config
+                .users
+                .iter()
+                .enumerate()
+                .filter(|(other, _)| *other != user)
+                .map(|(_, user)| user.display_name.clone())
+                .collect();
+
+            (
+                Self {
+                    display_name: config.users[user].display_name.clone(),
+                    auth_method: config.users[user].auth_method.clone(),
+                    default_cluster: config.default_cluster,
+                    taken_names,
+                    display_name_error: None,
+                    auth_error: false,
+                    confirm_delete: false,
+                },
+                operation::focus(Self::DISPLAY_NAME_ID),
+            )
+        }
+
+        pub fn update(&mut self, message: Message) -> Action {
+            match message {
+                Message::DisplayName(display_name) => {
+                    self.display_name = display_name;
+                    self.display_name_error = None;
+                }
+                Message::Username(name) => {
+                    if let AuthMethod::Password { username, .. } = &mut self.auth_method {
+                        *username = name;
+                        self.auth_error = false;
+                    }
+                }
+                Message::Realm(name) => {
+                    if let AuthMethod::Password { realm, .. } = &mut self.auth_method {
+                        *realm = name;
+                        self.auth_error = false;
+                    }
+                }
+                Message::Password => {
+                    if !matches!(self.auth_method, AuthMethod::Password { .. }) {
+                        self.auth_method = AuthMethod::default();
+                        self.auth_error = false;
+                    }
+                }
+                Message::Api => {
+                    if !matches!(self.auth_method, AuthMethod::ApiToken(_)) {
+                        self.auth_method = AuthMethod::ApiToken(Secret::default());
+                        self.auth_error = false;
+                    }
+                }
+                Message::Token(token) => {
+                    if let AuthMethod::ApiToken(current) = &mut self.auth_method {
+                        *current = token;
+                        self.auth_error = false;
+                    }
+                }
+                Message::DefaultCluster(cluster) => self.default_cluster = Some(cluster),
+                Message::Delete if self.confirm_delete => return Action::Delete,
+                Message::Delete => self.confirm_delete = true,
+                Message::CancelDelete => self.confirm_delete = false,
+                Message::Save => {
+                    if self.validate() {
+                        return Action::Save {
+                            display_name: self.display_name.clone(),
+                            auth_method: self.auth_method.clone(),
+                            default_cluster: self.default_cluster,
+                        };
+                    }
+                }
+                Message::Close => return Action::Close,
+            }
+
+            Action::None
+        }
+
+        // TODO: Better input validation
+        fn validate(&mut self) -> bool {
+            let display_name = self.display_name.trim();
+            self.display_name_error = if display_name.is_empty() {
+                Some("Invalid display name")
+            } else if self.taken_names.iter().any(|taken| taken == display_name) {
+                Some("Another user has this display name")
+            } else {
+                None
+            };
+
+            self.auth_error = match &self.auth_method {
+                AuthMethod::Password { username, realm } => username.is_empty() || realm.is_empty(),
+                AuthMethod::ApiToken(token) => token.is_empty(),
+            };
+
+            self.display_name_error.is_none() && !self.auth_error
+        }
+
+        pub fn view<'a>(&'a self, config: &'a Config) -> Element<'a, Message> {
+            fn error(message: &str) -> text::Text<'_> {
+                text(message).style(|theme: &Theme| text::Style {
+                    color: Some(theme.palette().danger),
+                })
+            }
+
+            let display_name = column![
+                text_input("Display Name", &self.display_name)
+                    .on_input(Message::DisplayName)
+                    .on_submit(Message::Save)
+                    .id(Self::DISPLAY_NAME_ID),
+                self.display_name_error.map(error),
+            ];
+
+            let password = button("Password");
+            let api = button("API Token");
+            let buttons = match self.auth_method {
+                AuthMethod::Password { .. } => row![password, api.on_press(Message::Api)],
+                AuthMethod::ApiToken(_) => row![password.on_press(Message::Password), api],
+            };
+
+            let auth_method: Element<'a, Message> = match &self.auth_method {
+                AuthMethod::Password { username, realm } => row![
+                    text_input("Username", username)
+                        .on_input(Message::Username)
+                        .on_submit(Message::Save),
+                    text("@"),
+                    text_input("Realm", realm)
+                        .on_input(Message::Realm)
+                        .on_submit(Message::Save)
+                        .width(100),
+                ]
+                .spacing(4)
+                .align_y(Center)
+                .into(),
+                AuthMethod::ApiToken(token) => text_input("API Token", token.expose())
+                    .on_input(|text| Message::Token(Secret::new(text)))
+                    .on_submit(Message::Save)
+                    .secure(true)
+                    .into(),
+            };
+
+            let clusters: Vec<ClusterChoice> = config
+                .clusters
+                .iter()
+                .enumerate()
+                .map(|(idx, cluster)| ClusterChoice {
+                    idx,
+                    name: &cluster.name,
+                })
+                .collect();
+            let selected = self
+                .default_cluster
+                .and_then(|idx| clusters.get(idx))
+                .cloned();
+
+            let default_cluster = row![
+                text("Default cluster"),
+                pick_list(clusters, selected, |cluster| {
+                    Message::DefaultCluster(cluster.idx)
+                })
+                .placeholder("None")
+                .width(Fill),
+            ]
+            .spacing(10)
+            .align_y(Center);
+
+            let delete: Element<'a, Message> = if self.confirm_delete {
+                row![
+                    text("Delete this user?"),
+                    button("Delete")
+                        .style(button::danger)
+                        .on_press(Message::Delete),
+                    button("Cancel")
+                        .style(button::secondary)
+                        .on_press(Message::CancelDelete),
+                ]
+                .spacing(6)
+                .align_y(Center)
+                .into()
+            } else {
+                button("Delete user")
+                    .style(button::danger)
+                    .on_press(Message::Delete)
+                    .into()
+            };
+
+            let content = column![
+                text("Settings").size(25),
+                display_name,
+                buttons,
+                auth_method,
+                self.auth_error.then(|| error("Invalid login details")),
+                default_cluster,
+                button("Save").on_press(Message::Save),
+                delete,
+            ]
+            .spacing(10)
+            .align_x(Center)
+            .width(Fill);
+
+            modal(container(content).center(400), Message::Close)
+                .padding(20)
+                .style(ui_box)
+                .svg_style(|theme, _| svg::Style {
+                    color: Some(theme.extended_palette().primary.base.text),
+                })
+                .into()
         }
     }
+}
 
-    pub fn view(user: &User) -> Element<'_, Message> {
-        modal(
-            container(user.display_name.as_str()).center(400),
-            Message::Close,
-        )
-        .style(ui_box)
-        .svg_style(|theme, _| svg::Style {
-            color: Some(theme.extended_palette().primary.base.text),
-        })
-        .into()
+mod usb_modal {
+    use crate::{modal::modal, styles::ui_box, usb::UsbDevice};
+    use iced::{
+        widget::{button, checkbox, column, container, row, scrollable, svg, text},
+        Center, Element, Fill,
+    };
+
+    #[derive(Debug)]
+    pub struct State {
+        vmid: u32,
+        devices: Vec<UsbDevice>,
+        selected: Vec<bool>,
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    pub enum Message {
+        Toggle(usize, bool),
+        Connect,
+        Close,
+    }
+
+    pub enum Action {
+        Connect(u32, Vec<UsbDevice>),
+        Close,
+        None,
+    }
+
+    impl State {
+        pub fn new(vmid: u32, devices: Vec<UsbDevice>) -> Self {
+            Self {
+                vmid,
+                selected: vec![false; devices.len()],
+                devices,
+            }
+        }
+
+        pub fn update(&mut self, message: Message) -> Action {
+            match message {
+                Message::Toggle(idx, selected) => {
+                    if let Some(current) = self.selected.get_mut(idx) {
+                        *current = selected;
+                    }
+                    Action::None
+                }
+                Message::Connect => Action::Connect(
+                    self.vmid,
+                    self.devices
+                        .iter()
+                        .zip(&self.selected)
+                        .filter(|(_, selected)| **selected)
+                        .map(|(device, _)| device.clone())
+                        .collect(),
+                ),
+                Message::Close => Action::Close,
+            }
+        }
+
+        pub fn view(&self) -> Element<'_, Message> {
+            let devices = self.devices.iter().zip(&self.selected).enumerate().map(
+                |(idx, (device, selected))| {
+                    checkbox(*selected)
+                        .label(device.to_string())
+                        .on_toggle(move |selected| Message::Toggle(idx, selected))
+                        .into()
+                },
+            );
+
+            let content = column![
+                text("Share USB devices").size(25),
+                scrollable(column(devices).spacing(6)).height(200),
+                row![
+                    button("Cancel")
+                        .style(button::secondary)
+                        .on_press(Message::Close),
+                    button("Connect").on_press(Message::Connect),
+                ]
+                .spacing(10),
+            ]
+            .spacing(10)
+            .align_x(Center)
+            .width(Fill);
+
+            modal(container(content).center(400), Message::Close)
+                .padding(20)
+                .style(ui_box)
+                .svg_style(|theme, _| svg::Style {
+                    color: Some(theme.extended_palette().primary.base.text),
+                })
+                .into()
+        }
     }
 }
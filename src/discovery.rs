@@ -0,0 +1,241 @@
+use crate::{
+    config::{Address, Cluster, Host},
+    proxmox::{self, Client},
+};
+use hickory_resolver::TokioAsyncResolver;
+use iced::futures::{stream, Stream, StreamExt};
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
+
+/// Smallest prefix of a subnet that is scanned, so a typo doesn't send out millions of requests.
+const MIN_PREFIX: u32 = 22;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+/// Candidates probed at the same time.
+const MAX_PROBES: usize = 32;
+/// `Server` header of the Proxmox API.
+const SERVER_HEADER: &str = "pve-api-daemon";
+/// Name of the cookie the login page of Proxmox VE sets, which no other server would mention.
+const AUTH_COOKIE: &str = "PVEAuthCookie";
+const TITLE_SUFFIX: &str = " - Proxmox Virtual Environment";
+/// Script the login page loads, with the version of Proxmox VE as its query.
+const VERSION_MARKER: &str = "pvemanagerlib.js?ver=";
+
+/// Address that may have a Proxmox API behind it.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub host: Host,
+    /// Name of the server in DNS, if it's already known.
+    pub name: Option<String>,
+}
+
+/// Server that answered on the Proxmox API.
+#[derive(Clone, Debug)]
+pub struct Server {
+    pub host: Host,
+    /// Name of the node, or of the server in DNS if the login page doesn't tell.
+    pub name: Option<String>,
+    /// Version of Proxmox VE, if the login page tells.
+    pub version: Option<String>,
+    /// Fingerprint of the certificate of the server, unless it's signed by a trusted CA.
+    pub fingerprint: Option<String>,
+}
+
+/// Lists the addresses to probe for `search`, which is either a subnet like `192.168.1.0/24` or
+/// the name of an SRV record like `_pve._tcp.example.com`.
+pub async fn candidates(search: String) -> Result<Vec<Candidate>, String> {
+    let search = search.trim();
+
+    if search.contains('/') {
+        return subnet(search);
+    }
+
+    let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(|err| err.to_string())?;
+    let records = resolver
+        .srv_lookup(search)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(records
+        .iter()
+        .filter_map(|record| {
+            let name = record.target().to_utf8();
+            let name = name.trim_end_matches('.');
+
+            Some(Candidate {
+                host: Host::new(Address::parse(name)?, record.port()),
+                name: Some(name.to_owned()),
+            })
+        })
+        .collect())
+}
+
+/// Every address of an IPv4 subnet, on the default port of the API.
+fn subnet(subnet: &str) -> Result<Vec<Candidate>, String> {
+    let invalid = || format!("Invalid subnet {subnet}");
+
+    let (address, prefix) = subnet.split_once('/').ok_or_else(invalid)?;
+    let address: Ipv4Addr = address.parse().map_err(|_| invalid())?;
+    let prefix: u32 = prefix
+        .parse()
+        .ok()
+        .filter(|prefix| *prefix <= 32)
+        .ok_or_else(invalid)?;
+    if prefix < MIN_PREFIX {
+        return Err(format!("Subnets bigger than /{MIN_PREFIX} aren't scanned"));
+    }
+
+    let size = 1 << (32 - prefix);
+    let network = u32::from(address) & !(size - 1);
+    // The first and last addresses are reserved, except in the smallest subnets
+    let offsets = if size > 2 { 1..size - 1 } else { 0..size };
+
+    Ok(offsets
+        .map(|offset| Candidate {
+            host: Host::new(
+                Address::Ip(IpAddr::V4(Ipv4Addr::from(network + offset))),
+                Host::DEFAULT_PORT,
+            ),
+            name: None,
+        })
+        .collect())
+}
+
+/// Probes the candidates a few at a time, yielding the servers as they answer and `None` for
+/// every candidate that doesn't turn out to be a Proxmox server.
+pub fn probe_all(candidates: Vec<Candidate>) -> impl Stream<Item = Option<Server>> {
+    stream::iter(candidates)
+        .map(probe)
+        .buffer_unordered(MAX_PROBES)
+}
+
+/// Fetches the login page of the candidate without logging in, returning the server if it's the
+/// one of Proxmox VE.
+async fn probe(candidate: Candidate) -> Option<Server> {
+    let page = tokio::time::timeout(PROBE_TIMEOUT, proxmox::login_page(&candidate.host))
+        .await
+        .ok()?
+        .ok()?;
+
+    let is_proxmox = page
+        .server
+        .as_deref()
+        .is_some_and(|server| server.starts_with(SERVER_HEADER))
+        || page.body.contains(AUTH_COOKIE);
+    if !is_proxmox {
+        return None;
+    }
+
+    // Certificates signed by a trusted CA are trusted without pinning them, which would break
+    // once they're renewed
+    let cluster = Cluster {
+        hosts: vec![candidate.host.clone()],
+        ..Cluster::default()
+    };
+    let trusted = !matches!(
+        tokio::time::timeout(PROBE_TIMEOUT, Client::new(&cluster).version()).await,
+        Ok(Err(proxmox::Error::Untrusted))
+    );
+
+    Some(Server {
+        name: node_name(&page.body).or_else(|| match candidate.host.address() {
+            Address::Dns(name) => Some(name.clone()),
+            Address::Ip(_) => candidate.name,
+        }),
+        version: version(&page.body),
+        fingerprint: page
+            .fingerprint
+            .filter(|_| !trusted)
+            .map(|fingerprint| proxmox::format_fingerprint(&fingerprint)),
+        host: candidate.host,
+    })
+}
+
+/// Name of the node from the title of the login page, like `pve1 - Proxmox Virtual Environment`.
+fn node_name(page: &str) -> Option<String> {
+    let (_, title) = page.split_once("<title>")?;
+    let (title, _) = title.split_once("</title>")?;
+    let name = title.trim().strip_suffix(TITLE_SUFFIX)?;
+
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
+fn version(page: &str) -> Option<String> {
+    let (_, version) = page.split_once(VERSION_MARKER)?;
+    let version: String = version
+        .chars()
+        .take_while(|char| char.is_ascii_alphanumeric() || matches!(char, '.' | '-'))
+        .collect();
+
+    (!version.is_empty()).then_some(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addresses(candidates: Result<Vec<Candidate>, String>) -> Result<Vec<String>, String> {
+        candidates.map(|candidates| {
+            candidates
+                .into_iter()
+                .map(|candidate| candidate.host.address().to_string())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn subnet_leaves_out_reserved_addresses() {
+        let candidates = addresses(subnet("192.168.1.77/30"));
+
+        assert_eq!(
+            candidates,
+            Ok(vec!["192.168.1.77".to_owned(), "192.168.1.78".to_owned()])
+        );
+    }
+
+    #[test]
+    fn subnet_keeps_all_addresses_of_the_smallest_subnets() {
+        assert_eq!(
+            addresses(subnet("10.0.0.5/31")),
+            Ok(vec!["10.0.0.4".to_owned(), "10.0.0.5".to_owned()])
+        );
+        assert_eq!(
+            addresses(subnet("10.0.0.5/32")),
+            Ok(vec!["10.0.0.5".to_owned()])
+        );
+    }
+
+    #[test]
+    fn subnet_counts_the_hosts_of_the_biggest_subnets() {
+        assert_eq!(
+            subnet("10.0.0.0/22").map(|candidates| candidates.len()),
+            Ok(1022)
+        );
+        assert_eq!(
+            subnet("10.0.0.0/21").map(|candidates| candidates.len()),
+            Err("Subnets bigger than /22 aren't scanned".to_owned())
+        );
+    }
+
+    #[test]
+    fn subnet_rejects_invalid_subnets() {
+        for invalid in ["10.0.0.0", "10.0.0/24", "10.0.0.0/33", "fe80::/64"] {
+            assert_eq!(
+                subnet(invalid).map(|candidates| candidates.len()),
+                Err(format!("Invalid subnet {invalid}"))
+            );
+        }
+    }
+
+    #[test]
+    fn login_page_tells_the_node_and_version() {
+        let page = "<html><head><title>pve1 - Proxmox Virtual Environment</title>\
+            <script src=\"/pve2/js/pvemanagerlib.js?ver=8.2.4\"></script>";
+
+        assert_eq!(node_name(page), Some("pve1".to_owned()));
+        assert_eq!(version(page), Some("8.2.4".to_owned()));
+        assert_eq!(node_name("<title>Proxmox Backup Server</title>"), None);
+        assert_eq!(version("<title>pve1</title>"), None);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{
+    config::Config,
+    lockout::{Account, Lockouts},
+    proxmox::{self, Client, Ticket},
+    secrets::Secret,
+    styles::ui_box,
+};
+use iced::{
+    alignment::Horizontal, widget::{button, center, column, container, opaque, operation, text, text_input}, Element, Fill,
+    Task,
+    Theme,
+};
+
+#[derive(Debug)]
+pub struct State {
+    cluster: usize,
+    user: usize,
+    password: Secret,
+    error: Option<String>,
+    pending: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum Message {
+    Password(Secret),
+    Submit,
+    Verified(Result<Ticket, proxmox::Error>),
+}
+
+#[derive(Debug)]
+pub enum Action {
+    Unlock(Ticket),
+    Run(Task<Message>),
+    None,
+}
+
+impl State {
+    const PASSWORD_ID: &str = "lock_password";
+
+    pub fn new(cluster: usize, user: usize) -> (Self, Task<Message>) {
+        (
+            Self {
+                cluster,
+                user,
+                password: Secret::default(),
+                error: None,
+                pending: false,
+            },
+            operation::focus(Self::PASSWORD_ID),
+        )
+    }
+
+    pub fn update(&mut self, message: Message, config: &Config, lockouts: &mut Lockouts) -> Action {
+        match message {
+            Message::Password(password) => {
+                self.password = password;
+                self.error = None;
+                Action::None
+            }
+            Message::Submit => {
+                if self.pending {
+                    return Action::None;
+                }
+
+                let Some(username) = config.users[self.user].auth_method.login_name() else {
+                    self.error = Some("User does not log in with a password".to_owned());
+                    return Action::None;
+                };
+                // Unlocking checks the password just like logging in, so it's limited the same way
+                if self
+                    .account(config)
+                    .is_some_and(|account| lockouts.remaining(&account).is_some())
+                {
+                    return Action::None;
+                }
+
+                if self.password.is_empty() {
+                    self.error = Some("Empty password is not valid".to_owned());
+                    return Action::None;
+                }
+
+                // The password is checked by the cluster rather than against the original login,
+                // so nothing about it has to be kept around while the session is locked
+                self.pending = true;
+                Action::Run(Task::perform(
+                    Client::new(&config.clusters[self.cluster])
+                        .login(username, std::mem::take(&mut self.password)),
+                    Message::Verified,
+                ))
+            }
+            Message::Verified(result) => {
+                self.pending = false;
+
+                let account = self.account(config);
+                match result {
+                    Ok(ticket) => {
+                        if let Some(account) = &account {
+                            lockouts.succeed(account);
+                        }
+                        Action::Unlock(ticket)
+                    }
+                    Err(proxmox::Error::Unauthorized) => {
+                        self.error = Some("Incorrect password".to_owned());
+                        if let Some(account) = &account {
+                            lockouts.fail(account, &config.kiosk);
+                        }
+                        Action::Run(operation::focus(Self::PASSWORD_ID))
+                    }
+                    Err(err) => {
+                        self.error = Some(err.to_string());
+                        Action::Run(operation::focus(Self::PASSWORD_ID))
+                    }
+                }
+            }
+        }
+    }
+
+    fn account(&self, config: &Config) -> Option<Account> {
+        Account::new(config, self.cluster, self.user)
+    }
+
+    pub fn view<'a>(&'a self, config: &'a Config, lockouts: &Lockouts) -> Element<'a, Message> {
+        let lockout = self
+            .account(config)
+            .and_then(|account| lockouts.countdown(&account));
+        let submit = (!self.pending && lockout.is_none()).then_some(Message::Submit);
+
+        let password_input = text_input("Password", self.password.expose())
+            .on_input(|text| Message::Password(Secret::new(text)))
+            .on_submit_maybe(submit.clone())
+            .secure(true)
+            .id(Self::PASSWORD_ID);
+
+        let unlock_button = button("Unlock").on_press_maybe(submit);
+
+        let error_message = lockout.or_else(|| self.error.clone()).map(|error| {
+            text(error).style(|theme: &Theme| text::Style {
+                color: Some(theme.palette().danger),
+            })
+        });
+
+        let input_box = container(
+            column![
+                text(config.users[self.user].to_string()).size(25),
+                text("Session locked"),
+                password_input,
+                error_message,
+                unlock_button,
+            ]
+            .spacing(10)
+            .align_x(Horizontal::Center),
+        )
+        .width(300)
+        .padding(20)
+        .style(ui_box);
+
+        opaque(
+            center(input_box)
+                .width(Fill)
+                .height(Fill)
+                .style(|theme: &Theme| {
+                    container::Style::default()
+                        .background(theme.extended_palette().background.base.color)
+                }),
+        )
+    }
+}
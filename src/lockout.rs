@@ -0,0 +1,247 @@
+use crate::{
+    config::{Config, ConfigManager, Kiosk},
+    secrets,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs::{self, File},
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const LOCKOUTS_FILE_NAME: &str = "lockouts.yaml";
+const AUDIT_LOG_FILE_NAME: &str = "audit.log";
+/// Lockouts stop doubling after this many, so the delay can't grow without bound.
+const MAX_LOCKOUT_DOUBLINGS: u32 = 10;
+
+/// Proxmox account that failed password logins are counted for, no matter which user logs in as
+/// it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Account {
+    /// Username including the realm.
+    pub login: String,
+    pub cluster: String,
+}
+
+impl Account {
+    /// Account the user logs in to on the cluster, unless they use an API token.
+    pub fn new(config: &Config, cluster: usize, user: usize) -> Option<Self> {
+        Some(Self {
+            login: config.users[user].auth_method.login_name()?,
+            cluster: config.clusters[cluster].name.clone(),
+        })
+    }
+}
+
+impl Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' on cluster '{}'", self.login, self.cluster)
+    }
+}
+
+/// Failed password logins by account, which lock the account out once there are too many.
+///
+/// They're kept in the config directory, so restarting doesn't reset them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Lockouts(Vec<Failures>);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Failures {
+    account: Account,
+    count: u32,
+    until: Option<SystemTime>,
+}
+
+impl Lockouts {
+    /// Reads the failed logins kept from before, which are empty if there are none.
+    pub fn load() -> Self {
+        ConfigManager::config_dir()
+            .and_then(|config_dir| fs::read_to_string(config_dir.join(LOCKOUTS_FILE_NAME)).ok())
+            .and_then(|lockouts| serde_yaml::from_str(&lockouts).ok())
+            .unwrap_or_default()
+    }
+
+    /// Time left until the account may be logged in to again.
+    pub fn remaining(&self, account: &Account) -> Option<Duration> {
+        self.0
+            .iter()
+            .find(|failures| failures.account == *account)?
+            .until?
+            .duration_since(SystemTime::now())
+            .ok()
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Tells how long the account is still locked out for.
+    pub fn countdown(&self, account: &Account) -> Option<String> {
+        self.remaining(account).map(|remaining| {
+            format!(
+                "Too many failed attempts, try again in {}s",
+                remaining.as_secs() + 1
+            )
+        })
+    }
+
+    /// Whether any account is locked out at the moment.
+    pub fn active(&self) -> bool {
+        self.0
+            .iter()
+            .any(|failures| self.remaining(&failures.account).is_some())
+    }
+
+    /// Counts a failed login, writing the lockout it may result in to the audit log.
+    pub fn fail(&mut self, account: &Account, kiosk: &Kiosk) {
+        let (count, lockout) = self.count_failure(account, kiosk, SystemTime::now());
+        self.save();
+
+        if let Some(lockout) = lockout {
+            let _ = write_audit_log(&format!(
+                "{account} locked out for {}s after {count} failed logins",
+                lockout.as_secs()
+            ));
+        }
+    }
+
+    pub fn succeed(&mut self, account: &Account) {
+        let before = self.0.len();
+        self.0.retain(|failures| failures.account != *account);
+
+        if self.0.len() != before {
+            self.save();
+        }
+    }
+
+    /// Counts a failed login at `now`, returning the failures so far and the lockout they result
+    /// in.
+    fn count_failure(
+        &mut self,
+        account: &Account,
+        kiosk: &Kiosk,
+        now: SystemTime,
+    ) -> (u32, Option<Duration>) {
+        let idx = self
+            .0
+            .iter()
+            .position(|failures| failures.account == *account)
+            .unwrap_or_else(|| {
+                self.0.push(Failures {
+                    account: account.clone(),
+                    count: 0,
+                    until: None,
+                });
+                self.0.len() - 1
+            });
+        let failures = &mut self.0[idx];
+        failures.count += 1;
+
+        if kiosk.max_login_failures == 0 || failures.count < kiosk.max_login_failures {
+            return (failures.count, None);
+        }
+
+        let doublings = (failures.count - kiosk.max_login_failures).min(MAX_LOCKOUT_DOUBLINGS);
+        let lockout = Duration::from_secs(kiosk.lockout_seconds.saturating_mul(1 << doublings));
+        failures.until = Some(now + lockout);
+        (failures.count, Some(lockout))
+    }
+
+    fn save(&self) -> Option<()> {
+        let config_dir = ConfigManager::config_dir()?;
+        fs::create_dir_all(&config_dir).ok()?;
+
+        let file = secrets::create_private(&config_dir.join(LOCKOUTS_FILE_NAME)).ok()?;
+        serde_yaml::to_writer(file, self).ok()
+    }
+}
+
+/// Appends a line to the audit log, so lockouts can be traced back afterwards.
+fn write_audit_log(message: &str) -> Option<()> {
+    let config_dir = ConfigManager::config_dir()?;
+    fs::create_dir_all(&config_dir).ok()?;
+
+    let mut file = File::options()
+        .create(true)
+        .append(true)
+        .open(config_dir.join(AUDIT_LOG_FILE_NAME))
+        .ok()?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+
+    writeln!(file, "[{timestamp}] {message}").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(login: &str) -> Account {
+        Account {
+            login: login.to_owned(),
+            cluster: "lab".to_owned(),
+        }
+    }
+
+    fn kiosk(max_login_failures: u32) -> Kiosk {
+        Kiosk {
+            max_login_failures,
+            lockout_seconds: 30,
+            ..Kiosk::default()
+        }
+    }
+
+    #[test]
+    fn lockout_doubles_after_the_allowed_failures() {
+        let mut lockouts = Lockouts::default();
+        let alice = account("alice@pve");
+        let now = SystemTime::now();
+
+        let lockouts: Vec<_> = (0..5)
+            .map(|_| lockouts.count_failure(&alice, &kiosk(3), now))
+            .collect();
+
+        assert_eq!(
+            lockouts,
+            [
+                (1, None),
+                (2, None),
+                (3, Some(Duration::from_secs(30))),
+                (4, Some(Duration::from_mins(1))),
+                (5, Some(Duration::from_mins(2))),
+            ]
+        );
+    }
+
+    #[test]
+    fn lockout_stops_doubling() {
+        let mut lockouts = Lockouts::default();
+        let alice = account("alice@pve");
+        let now = SystemTime::now();
+
+        let lockout = (0..20)
+            .map(|_| lockouts.count_failure(&alice, &kiosk(1), now).1)
+            .last()
+            .flatten();
+
+        assert_eq!(
+            lockout,
+            Some(Duration::from_secs(30 << MAX_LOCKOUT_DOUBLINGS))
+        );
+    }
+
+    #[test]
+    fn lockout_applies_to_the_failing_account_only() {
+        let mut lockouts = Lockouts::default();
+        let alice = account("alice@pve");
+        let bob = account("bob@pve");
+
+        lockouts.count_failure(&alice, &kiosk(1), SystemTime::now());
+        lockouts.count_failure(&bob, &kiosk(0), SystemTime::now());
+
+        assert!(lockouts.remaining(&alice).is_some());
+        assert_eq!(lockouts.remaining(&bob), None);
+        assert!(lockouts.active());
+    }
+}
@@ -1,13 +1,15 @@
 use crate::{
-    config::{AuthMethod, Config},
+    config::{shift_index, AuthMethod, Config},
     include_svg,
-    proxmox::{Auth, Ticket},
+    lockout::{Account, Lockouts},
+    proxmox::{self, Auth, Client, Ticket},
+    secrets::Secret,
     styles::ui_box,
 };
 use iced::{
     alignment::Horizontal, mouse::Interaction, widget::{
-        button, center, column, container, mouse_area, operation, pick_list, row, stack, svg, text,
-        text_input, Svg,
+        button, center, checkbox, column, container, mouse_area, operation, pick_list, row, stack,
+        svg, text, text_input, Svg,
     }, Element, Fill,
     Shrink,
     Task,
@@ -17,10 +19,13 @@ use iced::{
 include_svg!(OPEN_EYE, "lucide/eye.svg");
 include_svg!(CLOSED_EYE, "lucide/eye-off.svg");
 include_svg!(ADD_USER, "lucide/user-plus.svg");
+include_svg!(ADD_CLUSTER, "lucide/plus.svg");
+include_svg!(EDIT_CLUSTER, "lucide/pencil.svg");
 
 #[derive(Debug)]
 pub struct State {
     modal: Option<user_modal::State>,
+    cluster_modal: Option<cluster_modal::State>,
     cluster: Option<usize>,
     user: Option<usize>,
     password: Option<Password>,
@@ -28,9 +33,24 @@ pub struct State {
 
 #[derive(Debug)]
 struct Password {
-    text: String,
+    text: Secret,
     secure: bool,
-    error: bool,
+    /// Whether the password is remembered once it has been accepted.
+    remember: bool,
+    pending: bool,
+    error: Option<String>,
+}
+
+impl Password {
+    const fn new() -> Self {
+        Self {
+            text: Secret::new(String::new()),
+            secure: true,
+            remember: false,
+            pending: false,
+            error: None,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -39,10 +59,17 @@ pub enum Message {
     SelectUser(usize),
     ShowModal,
     Modal(user_modal::Message),
-    Password(String),
+    /// Opens the cluster modal for the cluster at the index, or for a new one.
+    ShowClusterModal(Option<usize>),
+    ClusterModal(cluster_modal::Message),
+    Password(Secret),
     ShowPassword,
     HidePassword,
+    RememberPassword(bool),
+    ForgetPassword,
     SubmitPassword,
+    /// The password of the user at the first index was checked by the cluster at the second.
+    PasswordChecked(usize, usize, Result<Ticket, proxmox::Error>),
     SubmitApi,
     Login(Auth),
 }
@@ -67,21 +94,33 @@ impl State {
 
         Self {
             modal: None,
+            cluster_modal: None,
             cluster: config.default_cluster,
             user,
             password: user.and_then(|idx| match config.users[idx].auth_method {
-                AuthMethod::Password { .. } => Some(Password {
-                    text: String::new(),
-                    secure: true,
-                    error: false,
-                }),
+                AuthMethod::Password { .. } => Some(Password::new()),
                 AuthMethod::ApiToken(_) => None,
             }),
         }
     }
 
-    pub fn update(&mut self, message: Message, config: &mut Config) -> Action {
+    pub fn update(
+        &mut self,
+        message: Message,
+        config: &mut Config,
+        lockouts: &mut Lockouts,
+        admin: bool,
+    ) -> Action {
         match message {
+            // The reply to a pending login belongs to the user and cluster it was started with
+            Message::SelectCluster(_) | Message::SelectUser(_)
+                if self
+                    .password
+                    .as_ref()
+                    .is_some_and(|password| password.pending) =>
+            {
+                Action::None
+            }
             Message::SelectCluster(cluster) => {
                 if self.cluster.is_none_or(|current| current != cluster) {
                     self.cluster = Some(cluster);
@@ -99,22 +138,18 @@ impl State {
                 self.modal = Some(state);
                 Action::Run(task.map(Message::Modal))
             }
-            Message::Modal(message) => {
-                if let Some(state) = &mut self.modal {
-                    match state.update(message) {
-                        user_modal::Action::Add(user) => {
-                            self.modal = None;
-                            config.users.push(user);
-                            self.select_user(config, config.users.len() - 1);
-
-                            return Action::SaveConfig;
-                        }
-                        user_modal::Action::Close => self.modal = None,
-                        user_modal::Action::None => {}
-                    }
-                }
+            Message::Modal(message) => self.update_modal(message, config),
+            // Clusters are only edited in admin mode, or anyone could point one at their own server
+            Message::ShowClusterModal(_) | Message::ClusterModal(_) if !admin => {
+                self.cluster_modal = None;
                 Action::None
             }
+            Message::ShowClusterModal(idx) => {
+                let (state, task) = cluster_modal::State::new(idx, config);
+                self.cluster_modal = Some(state);
+                Action::Run(task.map(Message::ClusterModal))
+            }
+            Message::ClusterModal(message) => self.update_cluster_modal(message, config),
             Message::Password(text) => {
                 if let Some(password) = &mut self.password {
                     password.text = text;
@@ -133,26 +168,28 @@ impl State {
                 }
                 Action::None
             }
-            Message::SubmitPassword => {
+            Message::RememberPassword(remember) => {
                 if let Some(password) = &mut self.password {
-                    if self.user.is_some() && !password.text.is_empty() {
-                        // TODO: replace with password login
-                        Action::Run(Task::done(Message::Login(Auth::Ticket(Ticket {
-                            csrf: String::new(),
-                            ticket: String::new(),
-                        }))))
-                    } else {
-                        password.error = true;
-                        Action::None
-                    }
-                } else {
-                    Action::None
+                    password.remember = remember;
                 }
+                Action::None
+            }
+            Message::ForgetPassword => {
+                if let Some(user) = self.user {
+                    config.users[user].remembered_password = None;
+                }
+                Action::SaveConfig
+            }
+            Message::SubmitPassword => self.submit_password(config, lockouts),
+            Message::PasswordChecked(user, cluster, result) => {
+                self.password_checked(user, cluster, result, config, lockouts)
             }
             Message::SubmitApi => {
                 if self.user.is_some() {
                     // TODO: replace with api login
-                    Action::Run(Task::done(Message::Login(Auth::ApiToken(String::new()))))
+                    Action::Run(Task::done(Message::Login(
+                        Auth::ApiToken(Secret::default()),
+                    )))
                 } else {
                     Action::None
                 }
@@ -173,19 +210,169 @@ impl State {
         }
     }
 
+    /// Logs in with the password that was typed in, or the remembered one.
+    fn submit_password(&mut self, config: &Config, lockouts: &Lockouts) -> Action {
+        let (Some(user), Some(password)) = (self.user, &mut self.password) else {
+            return Action::None;
+        };
+        if password.pending {
+            return Action::None;
+        }
+
+        let Some(cluster) = self.cluster else {
+            password.error = Some("Select a cluster".to_owned());
+            return Action::None;
+        };
+        if Account::new(config, cluster, user)
+            .is_some_and(|account| lockouts.remaining(&account).is_some())
+        {
+            return Action::None;
+        }
+        if let Some(remembered) = remembered_password(config, user) {
+            password.text = remembered.clone();
+        }
+        if password.text.is_empty() {
+            password.error = Some("Empty password is not valid".to_owned());
+            return Action::None;
+        }
+        let Some(username) = config.users[user].auth_method.login_name() else {
+            return Action::None;
+        };
+
+        password.pending = true;
+        password.error = None;
+        Action::Run(Task::perform(
+            Client::new(&config.clusters[cluster]).login(username, password.text.clone()),
+            move |result| Message::PasswordChecked(user, cluster, result),
+        ))
+    }
+
+    fn password_checked(
+        &mut self,
+        user: usize,
+        cluster: usize,
+        result: Result<Ticket, proxmox::Error>,
+        config: &mut Config,
+        lockouts: &mut Lockouts,
+    ) -> Action {
+        // Replies are dropped once the user was changed anyway, such as by adding another one
+        let Some(password) = self.password.as_mut().filter(|password| password.pending) else {
+            return Action::None;
+        };
+        password.pending = false;
+        let text = std::mem::take(&mut password.text);
+        let account = Account::new(config, cluster, user);
+
+        match result {
+            Ok(ticket) => {
+                if let Some(account) = &account {
+                    lockouts.succeed(account);
+                }
+                if password.remember && config.kiosk.remember_passwords {
+                    config.users[user].remembered_password = Some(text);
+                }
+                Action::Login {
+                    auth: Auth::Ticket(ticket),
+                    cluster,
+                    user,
+                }
+            }
+            Err(proxmox::Error::Unauthorized) => {
+                password.error = Some("Incorrect username or password".to_owned());
+
+                if let Some(account) = &account {
+                    lockouts.fail(account, &config.kiosk);
+                }
+
+                // A remembered password that is rejected has to be typed in again from now on
+                if config.users[user].remembered_password.take().is_some() {
+                    Action::SaveConfig
+                } else {
+                    Action::Run(operation::focus(Self::PASSWORD_ID))
+                }
+            }
+            Err(err) => {
+                password.error = Some(err.to_string());
+                Action::None
+            }
+        }
+    }
+
+    fn update_modal(&mut self, message: user_modal::Message, config: &mut Config) -> Action {
+        let Some(state) = &mut self.modal else {
+            return Action::None;
+        };
+
+        match state.update(message) {
+            user_modal::Action::Add(user) => {
+                self.modal = None;
+                config.users.push(*user);
+                self.select_user(config, config.users.len() - 1);
+
+                Action::SaveConfig
+            }
+            user_modal::Action::Close => {
+                self.modal = None;
+                Action::None
+            }
+            user_modal::Action::None => Action::None,
+        }
+    }
+
+    fn update_cluster_modal(
+        &mut self,
+        message: cluster_modal::Message,
+        config: &mut Config,
+    ) -> Action {
+        let Some(state) = &mut self.cluster_modal else {
+            return Action::None;
+        };
+
+        match state.update(message) {
+            cluster_modal::Action::Save {
+                idx: Some(idx),
+                cluster,
+            } => {
+                self.cluster_modal = None;
+                config.rename_cluster(idx, cluster.name.clone());
+                config.clusters[idx] = *cluster;
+                Action::SaveConfig
+            }
+            cluster_modal::Action::Save { idx: None, cluster } => {
+                self.cluster_modal = None;
+                config.clusters.push(*cluster);
+                self.cluster = Some(config.clusters.len() - 1);
+                Action::SaveConfig
+            }
+            cluster_modal::Action::Remove(idx) => {
+                self.cluster_modal = None;
+                config.remove_cluster(idx);
+                self.cluster = shift_index(self.cluster, idx);
+                Action::SaveConfig
+            }
+            cluster_modal::Action::Run(task) => Action::Run(task.map(Message::ClusterModal)),
+            cluster_modal::Action::Close => {
+                self.cluster_modal = None;
+                Action::None
+            }
+            cluster_modal::Action::None => Action::None,
+        }
+    }
+
     fn select_user(&mut self, config: &Config, user: usize) {
         self.user = Some(user);
         self.password = match config.users[user].auth_method {
-            AuthMethod::Password { .. } => Some(Password {
-                text: String::new(),
-                secure: true,
-                error: false,
-            }),
+            AuthMethod::Password { .. } => Some(Password::new()),
             AuthMethod::ApiToken(_) => None,
         };
     }
 
-    pub fn view<'a>(&'a self, config: &'a Config) -> Element<'a, Message> {
+    /// Closes what can only be used in admin mode.
+    pub fn leave_admin_mode(&mut self) {
+        self.cluster_modal = None;
+    }
+
+    fn view_cluster<'a>(&'a self, config: &'a Config, admin: bool) -> Element<'a, Message> {
         let cluster_select = pick_list(
             config.clusters.as_slice(),
             self.cluster.map(|idx| &config.clusters[idx]),
@@ -202,6 +389,96 @@ impl State {
         .placeholder("Select cluster")
         .width(Fill);
 
+        let edit = admin.then(|| {
+            row![
+                button(center(svg(ADD_CLUSTER.clone())))
+                    .on_press(Message::ShowClusterModal(None))
+                    .width(35)
+                    .padding(5),
+                button(center(svg(EDIT_CLUSTER.clone())))
+                    .on_press_maybe(self.cluster.map(|idx| Message::ShowClusterModal(Some(idx))))
+                    .width(35)
+                    .padding(5),
+            ]
+        });
+
+        row![cluster_select, edit].height(Shrink).into()
+    }
+
+    fn view_password<'a>(
+        p: &'a Password,
+        account: Option<&Account>,
+        config: &Config,
+        user: usize,
+        lockouts: &Lockouts,
+    ) -> Element<'a, Message> {
+        let lockout = account.and_then(|account| lockouts.countdown(account));
+        let error = lockout.clone().or_else(|| p.error.clone());
+        let error_message = error.map(|error| {
+            container(text(error).style(|theme: &Theme| text::Style {
+                color: Some(theme.palette().danger),
+            }))
+        });
+        let submit = (!p.pending && lockout.is_none()).then_some(Message::SubmitPassword);
+
+        if remembered_password(config, user).is_some() {
+            return column![
+                row![
+                    button("Login").on_press_maybe(submit),
+                    button("Forget password")
+                        .style(button::secondary)
+                        .on_press(Message::ForgetPassword),
+                ]
+                .spacing(10),
+                error_message,
+            ]
+            .spacing(6)
+            .align_x(Horizontal::Center)
+            .into();
+        }
+
+        let password_input = text_input("Password", p.text.expose())
+            .on_input(|text| Message::Password(Secret::new(text)))
+            .on_submit_maybe(submit)
+            .secure(p.secure)
+            .id(Self::PASSWORD_ID);
+
+        let eye_svg: Svg = svg(if p.secure {
+            OPEN_EYE.clone()
+        } else {
+            CLOSED_EYE.clone()
+        })
+        .style(|theme: &Theme, _| svg::Style {
+            color: Some(theme.extended_palette().background.base.text),
+        });
+        let show_button = mouse_area(container(eye_svg).center_x(35).center_y(Fill).padding(5))
+            .on_press(Message::ShowPassword)
+            .on_release(Message::HidePassword)
+            .interaction(Interaction::Pointer);
+
+        let remember = config.kiosk.remember_passwords.then(|| {
+            checkbox(p.remember)
+                .label("Remember password")
+                .on_toggle(Message::RememberPassword)
+        });
+
+        column![
+            row![password_input, show_button].height(Shrink),
+            error_message,
+            remember,
+        ]
+        .spacing(6)
+        .into()
+    }
+
+    pub fn view<'a>(
+        &'a self,
+        config: &'a Config,
+        lockouts: &Lockouts,
+        admin: bool,
+    ) -> Element<'a, Message> {
+        let cluster = self.view_cluster(config, admin);
+
         let user_select = pick_list(
             config.users.as_slice(),
             self.user.map(|idx| &config.users[idx]),
@@ -225,51 +502,20 @@ impl State {
 
         let user = row![user_select, add_user].height(Shrink);
 
-        let auth: Option<Element<Message>> = self.user.map(|_| {
+        let auth: Option<Element<Message>> = self.user.map(|user| {
             self.password.as_ref().map_or_else(
                 || button("Login").on_press(Message::SubmitApi).into(),
                 |p| {
-                    let password_input = text_input("Password", &p.text)
-                        .on_input(Message::Password)
-                        .on_submit(Message::SubmitPassword)
-                        .secure(p.secure)
-                        .id(Self::PASSWORD_ID);
-
-                    let eye_svg: Svg = svg(if p.secure {
-                        OPEN_EYE.clone()
-                    } else {
-                        CLOSED_EYE.clone()
-                    })
-                    .style(|theme: &Theme, _| svg::Style {
-                        color: Some(theme.extended_palette().background.base.text),
-                    });
-                    let show_button =
-                        mouse_area(container(eye_svg).center_x(35).center_y(Fill).padding(5))
-                            .on_press(Message::ShowPassword)
-                            .on_release(Message::HidePassword)
-                            .interaction(Interaction::Pointer);
-
-                    let error_message = if p.error {
-                        Some(container(text("Empty password is not valid").style(
-                            |theme: &Theme| text::Style {
-                                color: Some(theme.palette().danger),
-                            },
-                        )))
-                    } else {
-                        None
-                    };
-
-                    column![
-                        row![password_input, show_button].height(Shrink),
-                        error_message
-                    ]
-                    .into()
+                    let account = self
+                        .cluster
+                        .and_then(|cluster| Account::new(config, cluster, user));
+                    Self::view_password(p, account.as_ref(), config, user, lockouts)
                 },
             )
         });
 
         let input_box = container(
-            column![cluster_select, user, auth]
+            column![cluster, user, auth]
                 .spacing(10)
                 .align_x(Horizontal::Center),
         )
@@ -282,16 +528,29 @@ impl State {
             self.modal
                 .as_ref()
                 .map(|state| state.view().map(Message::Modal)),
+            self.cluster_modal
+                .as_ref()
+                .map(|state| state.view().map(Message::ClusterModal)),
         ]
         .into()
     }
 }
 
+/// Password remembered for the user, as long as the policy allows it.
+fn remembered_password(config: &Config, user: usize) -> Option<&Secret> {
+    config
+        .kiosk
+        .remember_passwords
+        .then(|| config.users[user].remembered_password.as_ref())
+        .flatten()
+}
+
 mod user_modal {
     use crate::{
         config::{AuthMethod, User},
         login::ui_box,
         modal::modal,
+        secrets::Secret,
     };
     use iced::{
         widget::{button, column, container, operation, row, svg, text, text_input}, Center, Element, Task,
@@ -313,13 +572,13 @@ mod user_modal {
         Username(String),
         Password,
         Api,
-        Token(String),
+        Token(Secret),
         Close,
         Submit,
     }
 
     pub enum Action {
-        Add(User),
+        Add(Box<User>),
         Close,
         None,
     }
@@ -332,9 +591,8 @@ mod user_modal {
                 Self {
                     user: User {
                         display_name: String::new(),
-                        auth_method: AuthMethod::Password {
-                            username: String::new(),
-                        },
+                        auth_method: AuthMethod::default(),
+                        ..User::default()
                     },
                     display_name_error: false,
                     username_error: false,
@@ -352,21 +610,19 @@ mod user_modal {
                     Action::None
                 }
                 Message::Username(name) => {
-                    if let AuthMethod::Password { username } = &mut self.user.auth_method {
+                    if let AuthMethod::Password { username, .. } = &mut self.user.auth_method {
                         *username = name;
                         self.username_error = false;
                     }
                     Action::None
                 }
                 Message::Password => {
-                    self.user.auth_method = AuthMethod::Password {
-                        username: String::new(),
-                    };
+                    self.user.auth_method = AuthMethod::default();
                     self.username_error = false;
                     Action::None
                 }
                 Message::Api => {
-                    self.user.auth_method = AuthMethod::ApiToken(String::new());
+                    self.user.auth_method = AuthMethod::ApiToken(Secret::default());
                     self.api_error = false;
                     Action::None
                 }
@@ -380,7 +636,7 @@ mod user_modal {
                 Message::Close => Action::Close,
                 Message::Submit => {
                     if self.validate() {
-                        Action::Add(mem::take(&mut self.user))
+                        Action::Add(Box::new(mem::take(&mut self.user)))
                     } else {
                         Action::None
                     }
@@ -392,7 +648,8 @@ mod user_modal {
         pub const fn validate(&mut self) -> bool {
             self.display_name_error = self.user.display_name.is_empty();
 
-            self.username_error = if let AuthMethod::Password { username } = &self.user.auth_method
+            self.username_error = if let AuthMethod::Password { username, .. } =
+                &self.user.auth_method
                 && username.is_empty()
             {
                 true
@@ -445,7 +702,7 @@ mod user_modal {
             };
 
             let auth_method = match &self.user.auth_method {
-                AuthMethod::Password { username } => with_error!(
+                AuthMethod::Password { username, .. } => with_error!(
                     text_input("Username", username.as_str())
                         .on_input(Message::Username)
                         .on_submit(Message::Submit),
@@ -453,8 +710,8 @@ mod user_modal {
                     "Invalid username"
                 ),
                 AuthMethod::ApiToken(token) => with_error!(
-                    text_input("API Token", token.as_str())
-                        .on_input(Message::Token)
+                    text_input("API Token", token.expose())
+                        .on_input(|text| Message::Token(Secret::new(text)))
                         .on_submit(Message::Submit),
                     self.api_error,
                     "Invalid API token"
@@ -474,3 +731,652 @@ mod user_modal {
         }
     }
 }
+
+mod cluster_modal {
+    use crate::{
+        config::{Address, Cluster, Config, Host},
+        discovery::{self, Candidate, Server},
+        login::ui_box,
+        modal::modal,
+        proxmox::{self, Client, Version},
+    };
+    use iced::{
+        widget::{
+            button, checkbox, column, container, operation, row, scrollable, svg, text, text_input,
+        },
+        Center, Element, Fill, Task, Theme,
+    };
+
+    #[derive(Debug)]
+    pub struct State {
+        /// Cluster being edited, or `None` when adding one.
+        idx: Option<usize>,
+        cluster: Cluster,
+        hosts: Vec<HostInput>,
+        /// Names of the other clusters, which have to differ from this one.
+        taken_names: Vec<String>,
+        name_error: Option<&'static str>,
+        hosts_error: bool,
+        confirm_remove: bool,
+        discovery: Discovery,
+    }
+
+    /// Search for servers on the network, which can be used as the hosts of the cluster.
+    #[derive(Debug, Default)]
+    struct Discovery {
+        search: String,
+        /// Candidates that haven't answered yet.
+        pending: usize,
+        /// Servers found, and whether they're selected.
+        servers: Vec<(Server, bool)>,
+        error: Option<String>,
+    }
+
+    #[derive(Debug)]
+    struct HostInput {
+        address: String,
+        port: String,
+        /// SHA-256 fingerprint of the certificate, for hosts without one signed by a trusted CA.
+        fingerprint: String,
+        error: bool,
+        testing: bool,
+        /// Outcome of the last connection test.
+        test: Option<Result<String, String>>,
+    }
+
+    impl HostInput {
+        fn new(address: String, port: u16) -> Self {
+            Self {
+                address,
+                port: port.to_string(),
+                fingerprint: String::new(),
+                error: false,
+                testing: false,
+                test: None,
+            }
+        }
+
+        fn parse(&mut self) -> Option<Host> {
+            let host = self.host();
+            self.error = host.is_none();
+            host
+        }
+
+        fn host(&self) -> Option<Host> {
+            let address = Address::parse(&self.address);
+            let port = self.port.trim().parse().ok().filter(|port| *port != 0);
+            let fingerprint = self.fingerprint.trim();
+            let fingerprint = if fingerprint.is_empty() {
+                Some(None)
+            } else {
+                proxmox::parse_fingerprint(fingerprint).map(|_| Some(fingerprint.to_owned()))
+            };
+
+            address
+                .zip(port)
+                .zip(fingerprint)
+                .map(|((address, port), fingerprint)| {
+                    Host::new(address, port).with_fingerprint(fingerprint)
+                })
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub enum Message {
+        Name(String),
+        FollowCluster(bool),
+        Address(usize, String),
+        Port(usize, String),
+        Fingerprint(usize, String),
+        AddHost,
+        RemoveHost(usize),
+        Test,
+        /// Result of testing the host, which is matched by its address, since hosts may have been
+        /// removed or edited in the meantime.
+        Tested(Host, Result<Version, proxmox::Error>),
+        Remove,
+        CancelRemove,
+        Search(String),
+        Discover,
+        Candidates(Result<Vec<Candidate>, String>),
+        Probed(Option<Server>),
+        ToggleServer(usize, bool),
+        UseServers,
+        Close,
+        Submit,
+    }
+
+    pub enum Action {
+        Save {
+            idx: Option<usize>,
+            cluster: Box<Cluster>,
+        },
+        Remove(usize),
+        Run(Task<Message>),
+        Close,
+        None,
+    }
+
+    fn error<'a>(message: impl text::IntoFragment<'a>) -> text::Text<'a> {
+        text(message).style(|theme: &Theme| text::Style {
+            color: Some(theme.palette().danger),
+        })
+    }
+
+    impl State {
+        const NAME_ID: &str = "cluster_name";
+
+        pub fn new(idx: Option<usize>, config: &Config) -> (Self, Task<Message>) {
+            let cluster = idx
+                .map(|idx| config.clusters[idx].clone())
+                .unwrap_or_default();
+
+            let mut hosts: Vec<HostInput> = cluster
+                .hosts
+                .iter()
+                .map(|host| HostInput {
+                    fingerprint: host.fingerprint().unwrap_or_default().to_owned(),
+                    ..HostInput::new(host.address().to_string(), host.port())
+                })
+                .collect();
+            if hosts.is_empty() {
+                hosts.push(HostInput::new(String::new(), Host::DEFAULT_PORT));
+            }
+
+            let taken_names = config
+                .clusters
+                .iter()
+                .enumerate()
+                .filter(|(other, _)| Some(*other) != idx)
+                .map(|(_, cluster)| cluster.name.clone())
+                .collect();
+
+            (
+                Self {
+                    idx,
+                    cluster,
+                    hosts,
+                    taken_names,
+                    name_error: None,
+                    hosts_error: false,
+                    confirm_remove: false,
+                    discovery: Discovery {
+                        search: config.discovery.clone(),
+                        ..Discovery::default()
+                    },
+                },
+                operation::focus(Self::NAME_ID),
+            )
+        }
+
+        pub fn update(&mut self, message: Message) -> Action {
+            match message {
+                Message::Name(name) => {
+                    self.cluster.name = name;
+                    self.name_error = None;
+                    Action::None
+                }
+                Message::FollowCluster(follow) => {
+                    self.cluster.fixed_hosts = !follow;
+                    Action::None
+                }
+                Message::Address(idx, address) => {
+                    if let Some(host) = self.hosts.get_mut(idx) {
+                        host.address = address;
+                        host.error = false;
+                        host.testing = false;
+                        host.test = None;
+                    }
+                    Action::None
+                }
+                Message::Port(idx, port) => {
+                    if let Some(host) = self.hosts.get_mut(idx) {
+                        host.port = port;
+                        host.error = false;
+                        host.testing = false;
+                        host.test = None;
+                    }
+                    Action::None
+                }
+                Message::Fingerprint(idx, fingerprint) => {
+                    if let Some(host) = self.hosts.get_mut(idx) {
+                        host.fingerprint = fingerprint;
+                        host.error = false;
+                        host.testing = false;
+                        host.test = None;
+                    }
+                    Action::None
+                }
+                Message::AddHost => {
+                    self.hosts
+                        .push(HostInput::new(String::new(), Host::DEFAULT_PORT));
+                    self.hosts_error = false;
+                    Action::None
+                }
+                Message::RemoveHost(idx) => {
+                    if idx < self.hosts.len() {
+                        self.hosts.remove(idx);
+                    }
+                    Action::None
+                }
+                Message::Test => Action::Run(self.test()),
+                Message::Tested(tested, result) => {
+                    let test = match result {
+                        Ok(version) => Ok(format!("Proxmox VE {}", version.version)),
+                        Err(proxmox::Error::Unauthorized) => {
+                            Ok("Reachable, login required".to_owned())
+                        }
+                        Err(err) => Err(err.to_string()),
+                    };
+
+                    for host in &mut self.hosts {
+                        if host.testing && host.host().as_ref() == Some(&tested) {
+                            host.testing = false;
+                            host.test = Some(test.clone());
+                        }
+                    }
+                    Action::None
+                }
+                Message::Remove => match self.idx {
+                    Some(idx) if self.confirm_remove => Action::Remove(idx),
+                    Some(_) => {
+                        self.confirm_remove = true;
+                        Action::None
+                    }
+                    None => Action::None,
+                },
+                Message::CancelRemove => {
+                    self.confirm_remove = false;
+                    Action::None
+                }
+                Message::Search(_)
+                | Message::Discover
+                | Message::Candidates(_)
+                | Message::Probed(_)
+                | Message::ToggleServer(..)
+                | Message::UseServers => self.discover(message),
+                Message::Close => Action::Close,
+                Message::Submit => self.submit(),
+            }
+        }
+
+        fn discover(&mut self, message: Message) -> Action {
+            let discovery = &mut self.discovery;
+
+            match message {
+                Message::Search(search) => {
+                    discovery.search = search;
+                    discovery.error = None;
+                }
+                Message::Discover if discovery.pending == 0 => {
+                    discovery.servers.clear();
+                    discovery.error = None;
+                    // Counts as pending until the candidates are known
+                    discovery.pending = 1;
+
+                    return Action::Run(Task::perform(
+                        discovery::candidates(discovery.search.clone()),
+                        Message::Candidates,
+                    ));
+                }
+                Message::Candidates(Ok(candidates)) => {
+                    discovery.pending = candidates.len();
+                    if candidates.is_empty() {
+                        discovery.error = Some("Nothing found".to_owned());
+                    }
+
+                    return Action::Run(Task::run(
+                        discovery::probe_all(candidates),
+                        Message::Probed,
+                    ));
+                }
+                Message::Candidates(Err(err)) => {
+                    discovery.pending = 0;
+                    discovery.error = Some(err);
+                }
+                Message::Probed(server) => {
+                    discovery.pending = discovery.pending.saturating_sub(1);
+                    if let Some(server) = server {
+                        discovery.servers.push((server, true));
+                    }
+                    if discovery.pending == 0 && discovery.servers.is_empty() {
+                        discovery.error = Some("No Proxmox servers found".to_owned());
+                    }
+                }
+                Message::ToggleServer(idx, selected) => {
+                    if let Some((_, current)) = discovery.servers.get_mut(idx) {
+                        *current = selected;
+                    }
+                }
+                Message::UseServers => {
+                    let selected: Vec<&Server> = discovery
+                        .servers
+                        .iter()
+                        .filter(|(_, selected)| *selected)
+                        .map(|(server, _)| server)
+                        .collect();
+                    if selected.is_empty() {
+                        return Action::None;
+                    }
+
+                    if self.cluster.name.trim().is_empty() {
+                        self.cluster.name = selected[0]
+                            .name
+                            .clone()
+                            .unwrap_or_else(|| selected[0].host.address().to_string());
+                        self.name_error = None;
+                    }
+                    self.hosts = selected
+                        .iter()
+                        .map(|server| {
+                            let mut input = HostInput::new(
+                                server.host.address().to_string(),
+                                server.host.port(),
+                            );
+                            input.fingerprint = server.fingerprint.clone().unwrap_or_default();
+                            input
+                        })
+                        .collect();
+                    self.hosts_error = false;
+                }
+                _ => {}
+            }
+
+            Action::None
+        }
+
+        /// Asks every host for its version on its own, so each one is reported separately.
+        fn test(&mut self) -> Task<Message> {
+            Task::batch(self.hosts.iter_mut().filter_map(|input| {
+                let host = input.parse()?;
+                input.testing = true;
+                input.test = None;
+
+                let cluster = Cluster {
+                    hosts: vec![host.clone()],
+                    ..Cluster::default()
+                };
+
+                Some(Task::perform(
+                    Client::new(&cluster).version(),
+                    move |result| Message::Tested(host, result),
+                ))
+            }))
+        }
+
+        // TODO: Better input validation
+        fn submit(&mut self) -> Action {
+            let name = self.cluster.name.trim();
+            self.name_error = if name.is_empty() {
+                Some("Invalid name")
+            } else if self.taken_names.iter().any(|taken| taken == name) {
+                Some("Another cluster has this name")
+            } else {
+                None
+            };
+            self.hosts_error = self.hosts.is_empty();
+
+            // Parse every host, so all invalid ones are marked
+            let hosts: Vec<Option<Host>> = self.hosts.iter_mut().map(HostInput::parse).collect();
+
+            match hosts.into_iter().collect::<Option<Vec<Host>>>() {
+                Some(hosts) if self.name_error.is_none() && !self.hosts_error => {
+                    let mut cluster = self.cluster.clone();
+                    name.clone_into(&mut cluster.name);
+                    cluster.hosts = hosts;
+
+                    Action::Save {
+                        idx: self.idx,
+                        cluster: Box::new(cluster),
+                    }
+                }
+                _ => Action::None,
+            }
+        }
+
+        fn view_discovery(&self) -> Element<'_, Message> {
+            let discovery = &self.discovery;
+
+            let search = row![
+                text_input("Subnet or SRV record", &discovery.search)
+                    .on_input(Message::Search)
+                    .on_submit(Message::Discover)
+                    .width(Fill),
+                button("Discover")
+                    .style(button::secondary)
+                    .on_press_maybe((discovery.pending == 0).then_some(Message::Discover)),
+            ]
+            .spacing(6)
+            .align_y(Center);
+
+            let servers = discovery
+                .servers
+                .iter()
+                .enumerate()
+                .map(|(idx, (server, selected))| {
+                    let name = server.name.as_deref().map_or_else(
+                        || server.host.to_string(),
+                        |name| format!("{name} ({})", server.host),
+                    );
+                    let version = server.version.as_ref().map_or_else(
+                        || "Proxmox VE".to_owned(),
+                        |version| format!("Proxmox VE {version}"),
+                    );
+
+                    checkbox(*selected)
+                        .label(format!("{name}, {version}"))
+                        .on_toggle(move |selected| Message::ToggleServer(idx, selected))
+                        .into()
+                });
+
+            let status = if discovery.pending > 0 {
+                Some(text("Searching...").size(12.5))
+            } else {
+                discovery
+                    .error
+                    .as_deref()
+                    .map(|error| text(error).size(12.5))
+            };
+
+            column![
+                text("Discover servers"),
+                search,
+                status,
+                (!discovery.servers.is_empty()).then(|| {
+                    column![
+                        scrollable(column(servers).spacing(4)).height(100),
+                        button("Use selected servers")
+                            .style(button::secondary)
+                            .on_press(Message::UseServers),
+                    ]
+                    .spacing(6)
+                    .align_x(Center)
+                }),
+            ]
+            .spacing(6)
+            .align_x(Center)
+            .into()
+        }
+
+        fn view_host(idx: usize, host: &HostInput) -> Element<'_, Message> {
+            let inputs = row![
+                text_input("IP address or DNS name", &host.address)
+                    .on_input(move |address| Message::Address(idx, address))
+                    .on_submit(Message::Submit)
+                    .width(Fill),
+                text_input("Port", &host.port)
+                    .on_input(move |port| Message::Port(idx, port))
+                    .on_submit(Message::Submit)
+                    .width(70),
+                button("Remove")
+                    .style(button::secondary)
+                    .on_press(Message::RemoveHost(idx)),
+            ]
+            .spacing(6)
+            .align_y(Center);
+            let fingerprint = text_input("Certificate fingerprint (SHA-256)", &host.fingerprint)
+                .on_input(move |fingerprint| Message::Fingerprint(idx, fingerprint))
+                .on_submit(Message::Submit)
+                .size(12.5);
+
+            let status: Option<Element<'_, Message>> = if host.error {
+                Some(error("Invalid address, port or fingerprint").into())
+            } else if host.testing {
+                Some(text("Testing...").size(12.5).into())
+            } else {
+                host.test.as_ref().map(|test| match test {
+                    Ok(message) => text(message.as_str()).size(12.5).into(),
+                    Err(message) => error(message.as_str()).size(12.5).into(),
+                })
+            };
+
+            column![inputs, fingerprint, status].spacing(2).into()
+        }
+
+        pub fn view(&self) -> Element<'_, Message> {
+            let name = column![
+                text_input("Name", &self.cluster.name)
+                    .on_input(Message::Name)
+                    .on_submit(Message::Submit)
+                    .id(Self::NAME_ID),
+                self.name_error.map(error),
+            ];
+
+            let hosts = self
+                .hosts
+                .iter()
+                .enumerate()
+                .map(|(idx, host)| Self::view_host(idx, host));
+
+            let remove = self.idx.map(|_| -> Element<'_, Message> {
+                if self.confirm_remove {
+                    row![
+                        text("Remove this cluster?"),
+                        button("Remove")
+                            .style(button::danger)
+                            .on_press(Message::Remove),
+                        button("Cancel")
+                            .style(button::secondary)
+                            .on_press(Message::CancelRemove),
+                    ]
+                    .spacing(6)
+                    .align_y(Center)
+                    .into()
+                } else {
+                    button("Remove cluster")
+                        .style(button::danger)
+                        .on_press(Message::Remove)
+                        .into()
+                }
+            });
+
+            let content = column![
+                text(if self.idx.is_some() {
+                    "Edit cluster"
+                } else {
+                    "Add cluster"
+                })
+                .size(25),
+                name,
+                text("Hosts"),
+                scrollable(column(hosts).spacing(6)).height(200),
+                self.hosts_error
+                    .then(|| error("At least one host is needed")),
+                checkbox(!self.cluster.fixed_hosts)
+                    .label("Add new nodes of the cluster as hosts after login")
+                    .on_toggle(Message::FollowCluster),
+                row![
+                    button("Add host")
+                        .style(button::secondary)
+                        .on_press(Message::AddHost),
+                    button("Test connection")
+                        .style(button::secondary)
+                        .on_press(Message::Test),
+                    button("Save").on_press(Message::Submit),
+                ]
+                .spacing(10),
+                self.view_discovery(),
+                remove,
+            ]
+            .spacing(10)
+            .align_x(Center)
+            .width(Fill);
+
+            modal(container(content).center(500), Message::Close)
+                .padding(20)
+                .style(ui_box)
+                .svg_style(|theme, _| svg::Style {
+                    color: Some(theme.extended_palette().primary.base.text),
+                })
+                .into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Cluster, User};
+
+    fn user(username: &str) -> User {
+        User {
+            display_name: username.to_owned(),
+            auth_method: AuthMethod::Password {
+                username: username.to_owned(),
+                realm: AuthMethod::default_realm(),
+            },
+            ..User::default()
+        }
+    }
+
+    #[test]
+    fn login_sticks_to_the_user_it_was_started_for() {
+        let mut config = Config {
+            clusters: vec![Cluster::default()],
+            default_cluster: Some(0),
+            users: vec![user("alice"), user("bob")],
+            ..Config::default()
+        };
+        config.kiosk.remember_passwords = true;
+        let mut lockouts = Lockouts::default();
+        let mut state = State::new(&config, Some(0));
+
+        for message in [
+            Message::Password(Secret::from("hunter2")),
+            Message::RememberPassword(true),
+            Message::SubmitPassword,
+            Message::SelectUser(1),
+        ] {
+            state.update(message, &mut config, &mut lockouts, false);
+        }
+        assert_eq!(state.user, Some(0));
+
+        let ticket = Ticket {
+            ticket: Secret::from("ticket"),
+            csrf: Secret::from("csrf"),
+        };
+        let action = state.update(
+            Message::PasswordChecked(0, 0, Ok(ticket)),
+            &mut config,
+            &mut lockouts,
+            false,
+        );
+
+        assert!(matches!(
+            action,
+            Action::Login {
+                cluster: 0,
+                user: 0,
+                ..
+            }
+        ));
+        assert_eq!(
+            config.users[0]
+                .remembered_password
+                .as_ref()
+                .map(Secret::expose),
+            Some("hunter2")
+        );
+        assert_eq!(config.users[1].remembered_password, None);
+    }
+}
@@ -1,14 +1,27 @@
+mod admin;
 mod config;
 mod connect;
+mod discovery;
+mod lock;
+mod lockout;
 mod login;
 mod modal;
+mod monitors;
+mod provision;
 mod proxmox;
+mod secrets;
 mod styles;
+mod supervisor;
+mod terminal;
+mod usb;
+mod viewer;
 
-use crate::config::{AuthMethod, Cluster, Config, ConfigManager, User};
+use crate::config::{Config, ConfigManager};
 use iced::{
-    event::{self, listen_with, Status}, keyboard::{self, key::Named, Key}, widget::operation, window::{Level, Settings},
+    event::{self, listen_raw, listen_with, Status},
+    time::{every, seconds}, keyboard::{self, key::Named, Key}, widget::{container, operation, stack, text}, window::{self, Level, Position, Settings},
     Element,
+    Fill,
     Subscription,
     Task,
     Theme,
@@ -34,9 +47,34 @@ macro_rules! include_svg {
 pub const NAME_TITLE: &str = "Antechamber";
 pub const NAME_LOWER: &str = "antechamber";
 
+/// Whether the app runs fullscreen on top of everything else and refuses to be closed outside of
+/// admin mode.
+const KIOSK: bool = !cfg!(feature = "dev_mode");
+
 // TODO: Add default guest user that is used when none is selected
 
 fn main() -> iced::Result {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == provision::IMPORT_FLAG) {
+        provision::import_cli(&args);
+    }
+    if args.iter().any(|arg| arg == provision::EXPORT_FLAG) {
+        provision::export_cli(&args);
+    }
+    if args.iter().any(|arg| arg == supervisor::FLAG) {
+        supervisor::supervise();
+    }
+
+    supervisor::install_panic_hook();
+
+    // Fullscreen windows cover the monitor they're placed on
+    let position = monitors::detect()
+        .into_iter()
+        .find(|monitor| monitor.primary)
+        .map_or_else(Position::default, |monitor| {
+            Position::Specific(monitor.position())
+        });
+
     iced::application(State::new, State::update, State::view)
         .title(NAME_TITLE)
         .theme(State::theme)
@@ -44,14 +82,17 @@ fn main() -> iced::Result {
         .window(Settings {
             // Not strictly needed for intended use case, but I'll probably set one eventually
             icon: None,
-            fullscreen: !cfg!(feature = "dev_mode"),
+            position,
+            fullscreen: KIOSK,
             minimizable: false,
-            level: if cfg!(feature = "dev_mode") {
-                Level::Normal
-            } else {
+            level: if KIOSK {
                 Level::AlwaysOnTop
+            } else {
+                Level::Normal
             },
             decorations: true,
+            // Close requests are handled by the kiosk policy instead
+            exit_on_close_request: false,
             ..Settings::default()
         })
         .run()
@@ -60,56 +101,56 @@ fn main() -> iced::Result {
 #[derive(Debug)]
 struct State {
     config_manager: ConfigManager,
+    /// Why the config on disk couldn't be loaded, in which case the default one is used.
+    config_error: Option<String>,
     screen: Screen,
+    lock: Option<lock::State>,
+    admin: Option<admin::State>,
+    /// Kept outside the screens, so logging out doesn't reset failed attempts.
+    lockouts: lockout::Lockouts,
     // TODO: integrate into config
     theme: Theme,
 }
 
 #[derive(Debug)]
 enum Screen {
-    Login(login::State),
-    Connect(connect::State),
+    Login(Box<login::State>),
+    Connect(Box<connect::State>),
 }
 
 #[derive(Clone, Debug)]
 enum Message {
     Login(login::Message),
     Connect(connect::Message),
+    Lock(lock::Message),
+    Admin(admin::Message),
+    ShowAdmin,
+    LockoutTick,
+    CloseRequested,
     FocusNext,
     FocusPrev,
 }
 
 impl State {
     pub fn new() -> Self {
-        // TODO: Load file and handle errors
-        // let config = Config::load_file(CONFIG_PATH).expect("Config error handling not implemented");
-        let config = Config {
-            default_cluster: Some(0),
-            clusters: vec![Cluster {
-                name: "Cluster1".to_owned(),
-                hosts: vec![],
-            }],
-            default_user: Some(0),
-            users: vec![
-                User {
-                    display_name: "User 1".to_owned(),
-                    auth_method: AuthMethod::Password {
-                        username: "user1".to_owned(),
-                    },
-                },
-                User {
-                    display_name: "User 2".to_owned(),
-                    auth_method: AuthMethod::ApiToken("PROXMOX-API-TOKEN".to_owned()),
-                },
-            ],
-            viewer_args: vec![],
+        let (config_manager, config_error) = match ConfigManager::load() {
+            Ok(config_manager) => (config_manager, None),
+            Err(err) => {
+                let error = format!("{err}, changes won't be saved");
+                eprintln!("{error}");
+                (ConfigManager::unsaved(Config::default()), Some(error))
+            }
         };
 
-        let screen = Screen::Login(login::State::new(&config, None));
+        let screen = Screen::Login(Box::new(login::State::new(&config_manager.config, None)));
 
         Self {
-            config_manager: ConfigManager::from_config(config).expect("Let's assume this is fine"),
+            config_manager,
+            config_error,
             screen,
+            lock: None,
+            admin: None,
+            lockouts: lockout::Lockouts::load(),
             theme: Theme::Ferra,
         }
     }
@@ -119,10 +160,20 @@ impl State {
     }
 
     pub fn subscription(&self) -> Subscription<Message> {
-        let screen_sub = if let Screen::Connect(state) = &self.screen {
-            Some(state.subscription().map(Message::Connect))
+        let screen_sub = match &self.screen {
+            Screen::Login(_) => Subscription::none(),
+            Screen::Connect(state) => state
+                .subscription(
+                    self.lock.is_some() || self.admin.as_ref().is_some_and(|admin| !admin.hidden()),
+                )
+                .map(Message::Connect),
+        };
+
+        // Counts down lockouts on the login screen and the lock
+        let lockout_sub = if self.lockouts.active() {
+            every(seconds(1)).map(|_| Message::LockoutTick)
         } else {
-            None
+            Subscription::none()
         };
 
         let focus_sub = listen_with(|event, status, _id| {
@@ -146,25 +197,51 @@ impl State {
             }
         });
 
-        if let Some(screen_sub) = screen_sub {
-            Subscription::batch([screen_sub, focus_sub])
-        } else {
-            focus_sub
-        }
+        let close_sub = window::close_requests().map(|_| Message::CloseRequested);
+
+        // Raw events, so the chord still works while a text input has focus
+        let emergency_sub = listen_raw(|event, _status, _id| {
+            if let event::Event::Keyboard(keyboard::Event::KeyPressed { key, modifiers, .. }) =
+                event
+            {
+                Some((key, modifiers))
+            } else {
+                None
+            }
+        })
+        .with(self.config_manager.config.kiosk.emergency_chord.clone())
+        .filter_map(|(chord, (key, modifiers))| {
+            chord.matches(&key, modifiers).then_some(Message::ShowAdmin)
+        });
+
+        Subscription::batch([screen_sub, lockout_sub, focus_sub, close_sub, emergency_sub])
     }
 
     pub fn update(&mut self, message: Message) -> Task<Message> {
         match message {
             Message::Login(message) => {
+                let admin = self.admin_mode();
                 if let Screen::Login(state) = &mut self.screen {
-                    match state.update(message, &mut self.config_manager.config) {
+                    match state.update(
+                        message,
+                        &mut self.config_manager.config,
+                        &mut self.lockouts,
+                        admin,
+                    ) {
                         login::Action::Login {
                             auth,
                             cluster,
                             user,
                         } => {
-                            let (state, task) = connect::State::new(auth, cluster, user);
-                            self.screen = Screen::Connect(state);
+                            // Logging in may have remembered or forgotten a password
+                            let _ = self.config_manager.save();
+                            let (state, task) = connect::State::new(
+                                auth,
+                                cluster,
+                                user,
+                                &self.config_manager.config,
+                            );
+                            self.screen = Screen::Connect(Box::new(state));
                             task.map(Message::Connect)
                         }
                         login::Action::Run(task) => task.map(Message::Login),
@@ -179,35 +256,159 @@ impl State {
                 }
             }
             Message::Connect(message) => {
+                let admin = self.admin_mode();
                 if let Screen::Connect(state) = &mut self.screen {
-                    match state.update(message, &self.config_manager.config) {
+                    match state.update(message, &mut self.config_manager.config, admin) {
+                        connect::Action::Lock { cluster, user } => {
+                            let (state, task) = lock::State::new(cluster, user);
+                            self.lock = Some(state);
+                            task.map(Message::Lock)
+                        }
                         connect::Action::Logout(user) => {
-                            self.screen = Screen::Login(login::State::new(
+                            self.screen = Screen::Login(Box::new(login::State::new(
                                 &self.config_manager.config,
                                 Some(user),
-                            ));
+                            )));
+                            Task::none()
+                        }
+                        connect::Action::DeleteUser(user) => {
+                            self.config_manager.config.remove_user(user);
+                            let _ = self.config_manager.save();
+                            self.screen = Screen::Login(Box::new(login::State::new(
+                                &self.config_manager.config,
+                                None,
+                            )));
                             Task::none()
                         }
                         connect::Action::Run(task) => task.map(Message::Connect),
+                        connect::Action::SaveConfig => {
+                            let _ = self.config_manager.save();
+                            Task::none()
+                        }
                         connect::Action::None => Task::none(),
                     }
                 } else {
                     Task::none()
                 }
             }
+            Message::Lock(message) => self.update_lock(message),
+            Message::Admin(message) => self.update_admin(message),
+            Message::ShowAdmin => {
+                if self.admin.is_some() {
+                    return self.update_admin(admin::Message::Show);
+                }
+
+                let (state, task) = admin::State::new();
+                self.admin = Some(state);
+                task.map(Message::Admin)
+            }
+            Message::LockoutTick => Task::none(),
+            Message::CloseRequested => {
+                if !KIOSK || self.admin_mode() {
+                    iced::exit()
+                } else {
+                    Task::none()
+                }
+            }
             Message::FocusNext => operation::focus_next(),
             Message::FocusPrev => operation::focus_previous(),
         }
     }
 
+    fn update_lock(&mut self, message: lock::Message) -> Task<Message> {
+        let Some(lock) = &mut self.lock else {
+            return Task::none();
+        };
+
+        match lock.update(message, &self.config_manager.config, &mut self.lockouts) {
+            lock::Action::Unlock(ticket) => {
+                self.lock = None;
+                // The fresh ticket from unlocking is as good as a renewal
+                self.update(Message::Connect(connect::Message::Ticket(ticket)))
+            }
+            lock::Action::Run(task) => task.map(Message::Lock),
+            lock::Action::None => Task::none(),
+        }
+    }
+
+    /// Whether admin mode is unlocked, even while its panel is hidden.
+    fn admin_mode(&self) -> bool {
+        self.admin.as_ref().is_some_and(admin::State::unlocked)
+    }
+
+    fn update_admin(&mut self, message: admin::Message) -> Task<Message> {
+        let Some(admin) = &mut self.admin else {
+            return Task::none();
+        };
+
+        match admin.update(message, &mut self.config_manager.config) {
+            admin::Action::Run(task) => task.map(Message::Admin),
+            admin::Action::SaveConfig => {
+                let _ = self.config_manager.save();
+                Task::none()
+            }
+            admin::Action::Quit => iced::exit(),
+            admin::Action::Close => {
+                self.admin = None;
+                if let Screen::Login(state) = &mut self.screen {
+                    state.leave_admin_mode();
+                }
+                Task::none()
+            }
+            admin::Action::None => Task::none(),
+        }
+    }
+
     pub fn view(&self) -> Element<'_, Message> {
         let screen = match &self.screen {
-            Screen::Login(state) => state.view(&self.config_manager.config).map(Message::Login),
+            Screen::Login(state) => state
+                .view(
+                    &self.config_manager.config,
+                    &self.lockouts,
+                    self.admin_mode(),
+                )
+                .map(Message::Login),
             Screen::Connect(state) => state
-                .view(&self.config_manager.config)
+                .view(&self.config_manager.config, self.admin_mode())
                 .map(Message::Connect),
         };
 
+        // The lock covers the screen without replacing it, so the session keeps being renewed
+        let screen = if let Some(lock) = &self.lock {
+            stack![
+                screen,
+                lock.view(&self.config_manager.config, &self.lockouts)
+                    .map(Message::Lock)
+            ]
+            .into()
+        } else {
+            screen
+        };
+
+        let screen = if let Some(admin) = &self.admin {
+            stack![
+                screen,
+                admin.view(&self.config_manager.config).map(Message::Admin)
+            ]
+            .into()
+        } else {
+            screen
+        };
+
+        let screen = if let Some(error) = &self.config_error {
+            stack![
+                screen,
+                container(text(error).style(|theme: &Theme| text::Style {
+                    color: Some(theme.palette().danger),
+                }))
+                .center_x(Fill)
+                .padding(10),
+            ]
+            .into()
+        } else {
+            screen
+        };
+
         if cfg!(feature = "dev_mode") {
             screen.explain(iced::color!(0xcc_cc_cc))
         } else {
@@ -0,0 +1,142 @@
+use iced::Point;
+use std::process::Command;
+
+/// Output of the client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Monitor {
+    /// Index `xrandr` lists the monitor with, which GTK and so the viewer number them by as well.
+    pub index: usize,
+    pub name: String,
+    pub primary: bool,
+    pub x: i16,
+    pub y: i16,
+}
+
+impl Monitor {
+    pub fn position(&self) -> Point {
+        Point::new(f32::from(self.x), f32::from(self.y))
+    }
+}
+
+/// Lists the connected outputs, which is empty if `xrandr` isn't available.
+///
+/// This waits for `xrandr`, so it has to run outside of `update`.
+pub fn detect() -> Vec<Monitor> {
+    Command::new("xrandr")
+        .arg("--listmonitors")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(parse)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parses a line like ` 0: +*DP-1 2560/597x1440/336+0+0  DP-1`, with `*` marking the primary one.
+fn parse(line: &str) -> Option<Monitor> {
+    let (index, monitor) = line.split_once(':')?;
+    let index = index.trim().parse().ok()?;
+    let mut fields = monitor.split_whitespace();
+
+    let name = fields.next()?.trim_start_matches('+');
+    let (primary, name) = name
+        .strip_prefix('*')
+        .map_or((false, name), |name| (true, name));
+
+    // Offsets follow the size, which is given in pixels and millimeters, each with its sign
+    let geometry = fields.next()?;
+    let offsets = &geometry[geometry.find(['+', '-'])?..];
+    let (x, y) = offsets.split_at(offsets[1..].find(['+', '-'])? + 1);
+    let x = x.parse().ok()?;
+    let y = y.parse().ok()?;
+
+    Some(Monitor {
+        index,
+        name: name.to_owned(),
+        primary,
+        x,
+        y,
+    })
+}
+
+/// Maps the displays of the guest to the `selected` monitors, in the format of virt-viewer's
+/// `monitor-mapping`, which numbers both from 1.
+///
+/// Monitors that aren't connected are skipped, and there's no mapping without any left.
+pub fn mapping(monitors: &[Monitor], selected: &[String]) -> Option<String> {
+    let mapping: Vec<String> = monitors
+        .iter()
+        .filter(|monitor| selected.contains(&monitor.name))
+        .enumerate()
+        .map(|(display, monitor)| format!("{}:{}", display + 1, monitor.index + 1))
+        .collect();
+
+    (!mapping.is_empty()).then(|| mapping.join(";"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTING: &str = "Monitors: 2
+ 0: +*DP-1 2560/597x1440/336+0+0  DP-1
+ 1: +HDMI-1 1920/527x1080/296+2560+0  HDMI-1
+";
+
+    fn monitors() -> Vec<Monitor> {
+        LISTING.lines().filter_map(parse).collect()
+    }
+
+    #[test]
+    fn parse_listed_monitors() {
+        assert_eq!(
+            monitors(),
+            [
+                Monitor {
+                    index: 0,
+                    name: "DP-1".to_owned(),
+                    primary: true,
+                    x: 0,
+                    y: 0,
+                },
+                Monitor {
+                    index: 1,
+                    name: "HDMI-1".to_owned(),
+                    primary: false,
+                    x: 2560,
+                    y: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_negative_offsets() {
+        let monitor = parse(" 2: +VGA-1 1024/300x768/200-1024+0  VGA-1");
+
+        assert_eq!(
+            monitor.map(|monitor| (monitor.x, monitor.y)),
+            Some((-1024, 0))
+        );
+    }
+
+    #[test]
+    fn mapping_numbers_displays_in_order_of_the_monitors() {
+        let selected = ["HDMI-1".to_owned(), "DP-1".to_owned()];
+
+        assert_eq!(mapping(&monitors(), &selected), Some("1:1;2:2".to_owned()));
+        assert_eq!(
+            mapping(&monitors(), &["HDMI-1".to_owned()]),
+            Some("1:2".to_owned())
+        );
+    }
+
+    #[test]
+    fn mapping_skips_disconnected_monitors() {
+        assert_eq!(mapping(&monitors(), &["DP-2".to_owned()]), None);
+    }
+}
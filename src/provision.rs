@@ -0,0 +1,588 @@
+use crate::{
+    config::{Cluster, Config, ConfigManager, User, ViewerProfile},
+    secrets::Secret,
+};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    fs,
+    path::Path,
+    process,
+};
+
+/// Imports a bundle into the config on disk instead of starting the app, as in
+/// `--import bundle.yaml --on-conflict rename`.
+pub const IMPORT_FLAG: &str = "--import";
+const ON_CONFLICT_FLAG: &str = "--on-conflict";
+/// Writes the config with secrets redacted to the given file, or to stdout without one, instead
+/// of starting the app.
+pub const EXPORT_FLAG: &str = "--export";
+const REDACTED: &str = "<redacted>";
+
+/// Part of a config that is rolled out to several clients, written in YAML or JSON.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Bundle {
+    #[serde(default)]
+    pub clusters: Vec<Cluster>,
+    #[serde(default)]
+    pub users: Vec<User>,
+    #[serde(default)]
+    pub viewer_profiles: BTreeMap<String, ViewerProfile>,
+}
+
+#[derive(Clone, Copy, Debug, PartialOrd, Ord, PartialEq, Eq)]
+pub enum Kind {
+    Cluster,
+    User,
+    ViewerProfile,
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cluster => write!(f, "Cluster"),
+            Self::User => write!(f, "User"),
+            Self::ViewerProfile => write!(f, "Viewer profile"),
+        }
+    }
+}
+
+/// Item of a bundle with the same name as one already in the config.
+#[derive(Clone, Debug, PartialOrd, Ord, PartialEq, Eq)]
+pub struct Conflict {
+    pub kind: Kind,
+    pub name: String,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} \"{}\"", self.kind, self.name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Resolution {
+    Replace,
+    #[default]
+    Skip,
+    /// Adds the item under a name that isn't taken yet.
+    Rename,
+}
+
+impl Resolution {
+    pub const ALL: [Self; 3] = [Self::Replace, Self::Skip, Self::Rename];
+
+    fn parse(resolution: &str) -> Option<Self> {
+        match resolution {
+            "replace" => Some(Self::Replace),
+            "skip" => Some(Self::Skip),
+            "rename" => Some(Self::Rename),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Replace => write!(f, "Replace"),
+            Self::Skip => write!(f, "Skip"),
+            Self::Rename => write!(f, "Rename"),
+        }
+    }
+}
+
+/// Number of items of a bundle that were merged into the config, by how.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Report {
+    pub added: usize,
+    pub replaced: usize,
+    pub skipped: usize,
+    pub renamed: usize,
+}
+
+impl Report {
+    const fn count(&mut self, resolution: Option<Resolution>) {
+        match resolution {
+            None => self.added += 1,
+            Some(Resolution::Replace) => self.replaced += 1,
+            Some(Resolution::Skip) => self.skipped += 1,
+            Some(Resolution::Rename) => self.renamed += 1,
+        }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} replaced, {} skipped, {} renamed",
+            self.added, self.replaced, self.skipped, self.renamed
+        )
+    }
+}
+
+impl Bundle {
+    /// Reads a bundle, which is JSON if the file ends in `.json` and YAML otherwise.
+    pub fn read(path: &str) -> Result<Self, String> {
+        let bundle = fs::read_to_string(path).map_err(|err| format!("Can't read {path}: {err}"))?;
+
+        // Enums are tagged differently in YAML, so JSON can't just be read as YAML
+        let bundle = if Path::new(path)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+        {
+            serde_json::from_str(&bundle).map_err(|err| err.to_string())
+        } else {
+            serde_yaml::from_str(&bundle).map_err(|err| err.to_string())
+        };
+
+        bundle.map_err(|err| format!("Invalid bundle {path}: {err}"))
+    }
+
+    /// Items with the same name as one in the config, which have to be resolved when merging.
+    pub fn conflicts(&self, config: &Config) -> Vec<Conflict> {
+        let clusters = self
+            .clusters
+            .iter()
+            .filter(|cluster| config.clusters.iter().any(|c| c.name == cluster.name))
+            .map(|cluster| Conflict {
+                kind: Kind::Cluster,
+                name: cluster.name.clone(),
+            });
+        let users = self
+            .users
+            .iter()
+            .filter(|user| {
+                config
+                    .users
+                    .iter()
+                    .any(|u| u.display_name == user.display_name)
+            })
+            .map(|user| Conflict {
+                kind: Kind::User,
+                name: user.display_name.clone(),
+            });
+        let viewer_profiles = self
+            .viewer_profiles
+            .keys()
+            .filter(|name| config.viewer_profiles.contains_key(*name))
+            .map(|name| Conflict {
+                kind: Kind::ViewerProfile,
+                name: name.clone(),
+            });
+
+        clusters.chain(users).chain(viewer_profiles).collect()
+    }
+
+    /// Adds every item to the config, resolving each conflict with `resolve`.
+    ///
+    /// Replaced items keep their place, so indices into the config stay valid.
+    pub fn merge(
+        mut self,
+        config: &mut Config,
+        resolve: impl Fn(&Conflict) -> Resolution,
+    ) -> Report {
+        let mut report = Report::default();
+
+        // Profiles go first, so clusters and users of the bundle follow them when they're renamed
+        for (name, profile) in self.viewer_profiles {
+            let resolution = config.viewer_profiles.contains_key(&name).then(|| {
+                resolve(&Conflict {
+                    kind: Kind::ViewerProfile,
+                    name: name.clone(),
+                })
+            });
+            report.count(resolution);
+
+            match resolution {
+                None | Some(Resolution::Replace) => {
+                    config.viewer_profiles.insert(name, profile);
+                }
+                Some(Resolution::Skip) => {}
+                Some(Resolution::Rename) => {
+                    let renamed =
+                        unique_name(&name, |name| config.viewer_profiles.contains_key(name));
+                    rename_profile(&mut self.clusters, &mut self.users, &name, &renamed);
+                    config.viewer_profiles.insert(renamed, profile);
+                }
+            }
+        }
+
+        for mut cluster in self.clusters {
+            let existing = config.clusters.iter().position(|c| c.name == cluster.name);
+            let resolution = existing.map(|_| {
+                resolve(&Conflict {
+                    kind: Kind::Cluster,
+                    name: cluster.name.clone(),
+                })
+            });
+            report.count(resolution);
+
+            match (existing, resolution) {
+                (Some(idx), Some(Resolution::Replace)) => config.clusters[idx] = cluster,
+                (Some(_), Some(Resolution::Skip)) => {}
+                (Some(_), _) => {
+                    let renamed = unique_name(&cluster.name, |name| {
+                        config.clusters.iter().any(|c| c.name == name)
+                    });
+                    rename_cluster(&mut self.users, &cluster.name, &renamed);
+                    cluster.name = renamed;
+                    config.clusters.push(cluster);
+                }
+                (None, _) => config.clusters.push(cluster),
+            }
+        }
+
+        for mut user in self.users {
+            let existing = config
+                .users
+                .iter()
+                .position(|u| u.display_name == user.display_name);
+            let resolution = existing.map(|_| {
+                resolve(&Conflict {
+                    kind: Kind::User,
+                    name: user.display_name.clone(),
+                })
+            });
+            report.count(resolution);
+
+            match (existing, resolution) {
+                (Some(idx), Some(Resolution::Replace)) => config.users[idx] = user,
+                (Some(_), Some(Resolution::Skip)) => {}
+                (Some(_), _) => {
+                    user.display_name = unique_name(&user.display_name, |name| {
+                        config.users.iter().any(|u| u.display_name == name)
+                    });
+                    config.users.push(user);
+                }
+                (None, _) => config.users.push(user),
+            }
+        }
+
+        report
+    }
+}
+
+/// Makes references to the viewer profile `from` point to `to` instead.
+fn rename_profile(clusters: &mut [Cluster], users: &mut [User], from: &str, to: &str) {
+    let profiles = clusters
+        .iter_mut()
+        .flat_map(|cluster| {
+            cluster
+                .viewer_profile
+                .iter_mut()
+                .chain(cluster.guest_viewer_profiles.values_mut())
+        })
+        .chain(
+            users
+                .iter_mut()
+                .flat_map(|user| user.viewer_profile.iter_mut()),
+        );
+
+    for profile in profiles.filter(|profile| *profile == from) {
+        to.clone_into(profile);
+    }
+}
+
+/// Makes the guests of users that are on the cluster `from` refer to `to` instead.
+fn rename_cluster(users: &mut [User], from: &str, to: &str) {
+    let guests = users
+        .iter_mut()
+        .flat_map(|user| user.favorites.iter_mut().chain(user.recent.iter_mut()));
+
+    for guest in guests.filter(|guest| guest.cluster == from) {
+        to.clone_into(&mut guest.cluster);
+    }
+}
+
+/// First of `name (2)`, `name (3)` and so on that isn't `taken`.
+fn unique_name(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    (2..usize::MAX)
+        .map(|number| format!("{name} ({number})"))
+        .find(|name| !taken(name))
+        .expect("Some number should be free")
+}
+
+/// Config layer a value came from.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Layer {
+    /// Written in the config file.
+    File,
+    /// Missing from the config file, so the default is used.
+    Default,
+}
+
+#[derive(Serialize)]
+struct Export {
+    config: Value,
+    /// Layer of every value of the config, by its path such as `clusters[0].name`.
+    layers: BTreeMap<String, Layer>,
+}
+
+/// Writes the effective config as YAML to be shared, with API tokens, remembered passwords and the
+/// admin password replaced by placeholders.
+pub fn export(config: &Config) -> Result<String, String> {
+    let mut config = config.clone();
+    for secret in config.users.iter_mut().flat_map(User::secrets_mut) {
+        *secret = Secret::from(REDACTED);
+    }
+    if let Some(password) = &mut config.kiosk.admin_password {
+        REDACTED.clone_into(password);
+    }
+
+    let config = serde_yaml::to_value(&config).map_err(|err| err.to_string())?;
+    let file: Option<Value> = ConfigManager::config_file()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|file| serde_yaml::from_str(&file).ok());
+
+    let mut layers = BTreeMap::new();
+    annotate(String::new(), &config, file.as_ref(), &mut layers);
+
+    serde_yaml::to_string(&Export { config, layers }).map_err(|err| err.to_string())
+}
+
+/// Records the layer of every value in `effective`, which came from the file if it's in `file`
+/// as well.
+fn annotate(
+    path: String,
+    effective: &Value,
+    file: Option<&Value>,
+    layers: &mut BTreeMap<String, Layer>,
+) {
+    // Enum variants are tagged, and their layer is that of their contents
+    let untag = |value: &Value| match value {
+        Value::Tagged(tagged) => tagged.value.clone(),
+        value => value.clone(),
+    };
+    let effective = untag(effective);
+    let file = file.map(untag);
+
+    match &effective {
+        Value::Mapping(mapping) if !mapping.is_empty() => {
+            for (key, value) in mapping {
+                let key = match key {
+                    Value::String(key) => key.clone(),
+                    key => serde_yaml::to_string(key)
+                        .unwrap_or_default()
+                        .trim()
+                        .to_owned(),
+                };
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                let file = file.as_ref().and_then(|file| file.get(key.as_str()));
+
+                annotate(path, value, file, layers);
+            }
+        }
+        Value::Sequence(sequence) if !sequence.is_empty() => {
+            for (idx, value) in sequence.iter().enumerate() {
+                let file = file.as_ref().and_then(|file| file.get(idx));
+
+                annotate(format!("{path}[{idx}]"), value, file, layers);
+            }
+        }
+        _ => {
+            let layer = if file.is_some() {
+                Layer::File
+            } else {
+                Layer::Default
+            };
+            layers.insert(path, layer);
+        }
+    }
+}
+
+/// Imports the bundle named in `args` into the config on disk and exits, resolving every
+/// conflict the same way, skipping by default.
+pub fn import_cli(args: &[String]) -> ! {
+    let mut args = args.iter();
+    let mut path = None;
+    let mut resolution = Resolution::Skip;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            IMPORT_FLAG => path = args.next(),
+            ON_CONFLICT_FLAG => {
+                let Some(parsed) = args.next().and_then(|arg| Resolution::parse(arg)) else {
+                    fail("--on-conflict has to be replace, skip or rename");
+                };
+                resolution = parsed;
+            }
+            arg => fail(&format!("Unknown argument {arg}")),
+        }
+    }
+
+    let Some(path) = path else {
+        fail("--import needs the path of a bundle");
+    };
+    let bundle = Bundle::read(path).unwrap_or_else(|err| fail(&err));
+    let mut config_manager = ConfigManager::load().unwrap_or_else(|err| fail(&err.to_string()));
+
+    for conflict in bundle.conflicts(&config_manager.config) {
+        eprintln!("{conflict} already exists: {resolution}");
+    }
+
+    let report = bundle.merge(&mut config_manager.config, |_| resolution);
+    if let Err(err) = config_manager.save() {
+        fail(&err.to_string());
+    }
+
+    println!("Imported {path}: {report}");
+    process::exit(0)
+}
+
+/// Exports the config on disk to the file named in `args`, or to stdout without one, and exits.
+pub fn export_cli(args: &[String]) -> ! {
+    let mut args = args.iter().skip_while(|arg| *arg != EXPORT_FLAG).skip(1);
+    let path = args.next().filter(|path| *path != "-");
+
+    let config_manager = ConfigManager::load().unwrap_or_else(|err| fail(&err.to_string()));
+    let export = export(&config_manager.config).unwrap_or_else(|err| fail(&err));
+
+    match path {
+        Some(path) => {
+            if let Err(err) = fs::write(path, export) {
+                fail(&format!("Can't write {path}: {err}"));
+            }
+        }
+        None => print!("{export}"),
+    }
+
+    process::exit(0)
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GuestRef;
+
+    fn cluster(name: &str, viewer_profile: Option<&str>) -> Cluster {
+        Cluster {
+            name: name.to_owned(),
+            viewer_profile: viewer_profile.map(str::to_owned),
+            ..Cluster::default()
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            clusters: vec![cluster("lab", None), cluster("prod", None)],
+            viewer_profiles: BTreeMap::from([("office".to_owned(), ViewerProfile::default())]),
+            ..Config::default()
+        }
+    }
+
+    fn names(config: &Config) -> Vec<&str> {
+        config
+            .clusters
+            .iter()
+            .map(|cluster| cluster.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn unique_name_numbers_from_two() {
+        let taken = ["lab", "lab (2)"];
+
+        assert_eq!(unique_name("lab", |name| taken.contains(&name)), "lab (3)");
+        assert_eq!(
+            unique_name("prod", |name| taken.contains(&name)),
+            "prod (2)"
+        );
+    }
+
+    #[test]
+    fn merge_resolves_conflicts() {
+        let bundle = Bundle {
+            clusters: vec![
+                cluster("lab", Some("new")),
+                cluster("prod", None),
+                cluster("test", None),
+            ],
+            ..Bundle::default()
+        };
+        let mut config = config();
+
+        let report = bundle.clone().merge(&mut config, |conflict| {
+            if conflict.name == "lab" {
+                Resolution::Replace
+            } else {
+                Resolution::Skip
+            }
+        });
+        assert_eq!(names(&config), ["lab", "prod", "test"]);
+        assert_eq!(config.clusters[0].viewer_profile.as_deref(), Some("new"));
+        assert_eq!(
+            report.to_string(),
+            "1 added, 1 replaced, 1 skipped, 0 renamed"
+        );
+
+        let report = bundle.merge(&mut config, |_| Resolution::Rename);
+        assert_eq!(
+            names(&config),
+            ["lab", "prod", "test", "lab (2)", "prod (2)", "test (2)"]
+        );
+        assert_eq!(
+            report.to_string(),
+            "0 added, 0 replaced, 0 skipped, 3 renamed"
+        );
+    }
+
+    #[test]
+    fn merge_follows_renamed_profiles() {
+        let bundle = Bundle {
+            clusters: vec![cluster("remote", Some("office"))],
+            viewer_profiles: BTreeMap::from([("office".to_owned(), ViewerProfile::default())]),
+            ..Bundle::default()
+        };
+        let mut config = config();
+
+        bundle.merge(&mut config, |_| Resolution::Rename);
+
+        assert!(config.viewer_profiles.contains_key("office (2)"));
+        assert_eq!(
+            config.clusters[2].viewer_profile.as_deref(),
+            Some("office (2)")
+        );
+    }
+
+    #[test]
+    fn merge_follows_renamed_clusters() {
+        let guest = |cluster: &str, vmid| GuestRef {
+            cluster: cluster.to_owned(),
+            vmid,
+        };
+        let bundle = Bundle {
+            clusters: vec![cluster("lab", None)],
+            users: vec![User {
+                display_name: "alice".to_owned(),
+                favorites: vec![guest("lab", 100), guest("remote", 101)],
+                recent: vec![guest("lab", 102)],
+                ..User::default()
+            }],
+            ..Bundle::default()
+        };
+        let mut config = config();
+
+        bundle.merge(&mut config, |_| Resolution::Rename);
+
+        assert_eq!(names(&config), ["lab", "prod", "lab (2)"]);
+        assert_eq!(
+            config.users[0].favorites,
+            [guest("lab (2)", 100), guest("remote", 101)]
+        );
+        assert_eq!(config.users[0].recent, [guest("lab (2)", 102)]);
+    }
+}
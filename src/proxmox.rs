@@ -1,40 +1,734 @@
-use serde::Deserialize;
-use std::fmt::{self, Display};
+use crate::{
+    config::{Cluster, Host},
+    secrets::Secret,
+};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use reqwest::{
+    header::{self, HeaderName},
+    redirect, Method, StatusCode, Upgraded, Url,
+};
+use ring::digest::{digest, SHA256};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{self, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    CertificateError, ClientConfig, DigitallySignedStruct, SignatureScheme,
+};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    io,
+    sync::{Arc, LazyLock, Mutex, PoisonError},
+};
+
+/// Client for hosts without a fingerprint, which need a certificate signed by a trusted CA.
+static HTTP: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .build()
+        .expect("TLS backend should be available")
+});
+
+/// Clients for hosts with a fingerprint, by that fingerprint.
+static PINNED: LazyLock<Mutex<HashMap<[u8; 32], reqwest::Client>>> = LazyLock::new(Mutex::default);
+
+/// HTTP client trusting the certificate of `host`.
+fn http(host: &Host) -> Result<reqwest::Client, Error> {
+    let Some(fingerprint) = host.fingerprint() else {
+        return Ok(HTTP.clone());
+    };
+    let fingerprint = parse_fingerprint(fingerprint).ok_or(Error::Untrusted)?;
+
+    let client = PINNED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(fingerprint)
+        .or_insert_with(|| {
+            reqwest::Client::builder()
+                .use_preconfigured_tls(tls_config(Some(fingerprint), Arc::default()))
+                .build()
+                .expect("TLS backend should be available")
+        })
+        .clone();
+
+    Ok(client)
+}
+
+/// TLS config trusting the certificate with the `expected` fingerprint, or any certificate
+/// without one, and telling the fingerprint of the certificate it `saw`.
+fn tls_config(expected: Option<[u8; 32]>, saw: Arc<Mutex<Option<[u8; 32]>>>) -> ClientConfig {
+    let provider = Arc::new(crypto::ring::default_provider());
+
+    ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .expect("Default protocol versions should be supported")
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(FingerprintVerifier {
+            expected,
+            saw,
+            provider,
+        }))
+        .with_no_client_auth()
+}
+
+/// Formats a SHA-256 fingerprint the way Proxmox shows it.
+pub fn format_fingerprint(fingerprint: &[u8; 32]) -> String {
+    fingerprint
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Parses a SHA-256 fingerprint as Proxmox shows it, hex digits separated by colons.
+pub fn parse_fingerprint(fingerprint: &str) -> Option<[u8; 32]> {
+    let hex: Vec<u8> = fingerprint
+        .bytes()
+        .filter(|byte| !matches!(byte, b':' | b' '))
+        .collect();
+    if hex.len() != 64 {
+        return None;
+    }
+
+    let mut bytes = [0; 32];
+    for (byte, pair) in bytes.iter_mut().zip(hex.chunks_exact(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+
+    Some(bytes)
+}
+
+/// Trusts exactly the certificate with the expected fingerprint, like Proxmox hosts with
+/// self-signed certificates have to be.
+#[derive(Debug)]
+struct FingerprintVerifier {
+    /// Fingerprint of the only trusted certificate, or `None` to trust any certificate.
+    expected: Option<[u8; 32]>,
+    /// Fingerprint of the last certificate that was presented.
+    saw: Arc<Mutex<Option<[u8; 32]>>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let mut fingerprint = [0; 32];
+        fingerprint.copy_from_slice(digest(&SHA256, end_entity).as_ref());
+        *self.saw.lock().unwrap_or_else(PoisonError::into_inner) = Some(fingerprint);
+
+        if self.expected.is_none_or(|expected| expected == fingerprint) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Whether a request failed because the certificate of the host isn't trusted.
+fn is_untrusted(err: &reqwest::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+
+    while let Some(err) = source {
+        if let Some(rustls::Error::InvalidCertificate(_)) = err.downcast_ref() {
+            return true;
+        }
+        // IO errors skip the error they wrap when asked for their source
+        source = err
+            .downcast_ref::<io::Error>()
+            .and_then(io::Error::get_ref)
+            .map(|inner| inner as &(dyn std::error::Error + 'static))
+            .or_else(|| err.source());
+    }
+
+    false
+}
+
+/// What a host serves to anyone who isn't logged in.
+#[derive(Clone, Debug)]
+pub struct LoginPage {
+    /// `Server` header of the response.
+    pub server: Option<String>,
+    pub body: String,
+    /// Fingerprint of the certificate of the host.
+    pub fingerprint: Option<[u8; 32]>,
+}
+
+/// Fetches the login page of `host` without any credentials, whatever its certificate, so a
+/// server can be recognized before it's known whether to trust it.
+pub async fn login_page(host: &Host) -> Result<LoginPage, Error> {
+    let saw = Arc::default();
+    let client = reqwest::Client::builder()
+        .use_preconfigured_tls(tls_config(None, Arc::clone(&saw)))
+        .redirect(redirect::Policy::none())
+        .build()
+        .expect("TLS backend should be available");
+
+    let response = client
+        .get(format!("https://{host}/"))
+        .send()
+        .await
+        .map_err(|err| Error::Unreachable(err.to_string()))?;
+    let server = response
+        .headers()
+        .get(header::SERVER)
+        .and_then(|server| server.to_str().ok())
+        .map(ToOwned::to_owned);
+    let body = response
+        .text()
+        .await
+        .map_err(|err| Error::Parse(err.to_string()))?;
+
+    let fingerprint = *saw.lock().unwrap_or_else(PoisonError::into_inner);
+    Ok(LoginPage {
+        server,
+        body,
+        fingerprint,
+    })
+}
+
+/// Connection to the Proxmox API of a single cluster.
+///
+/// Requests are sent to the hosts of the cluster in order, falling through to the next host
+/// when one is unreachable.
+#[derive(Clone, Debug)]
+pub struct Client {
+    hosts: Vec<Host>,
+    auth: Option<Auth>,
+}
+
+impl Client {
+    pub fn new(cluster: &Cluster) -> Self {
+        Self {
+            hosts: cluster.hosts.clone(),
+            auth: None,
+        }
+    }
+
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Requests a new ticket for `username`, which must include the realm.
+    pub async fn login(self, username: String, password: Secret) -> Result<Ticket, Error> {
+        self.request(
+            Method::POST,
+            "/access/ticket",
+            &[
+                ("username", username.as_str()),
+                ("password", password.expose()),
+            ],
+        )
+        .await
+    }
+
+    /// Asks for the version of Proxmox, which is mostly useful to check whether the hosts are
+    /// reachable.
+    ///
+    /// The version is only shown to authenticated clients, so [`Error::Unauthorized`] means that
+    /// a host was reached as well.
+    pub async fn version(self) -> Result<Version, Error> {
+        self.request(Method::GET, "/version", &[]).await
+    }
+
+    /// Starts `action` on the guest, returning the UPID of the task carrying it out.
+    pub async fn power(self, guest: Guest, action: PowerAction) -> Result<String, Error> {
+        self.request(
+            Method::POST,
+            &format!("{}/status/{}", guest.path(), action.endpoint()),
+            &[],
+        )
+        .await
+    }
+
+    pub async fn task_status(self, node: String, upid: String) -> Result<TaskStatus, Error> {
+        self.request(
+            Method::GET,
+            &format!("/nodes/{node}/tasks/{upid}/status"),
+            &[],
+        )
+        .await
+    }
+
+    /// Lists the guests of the cluster, leaving out templates.
+    pub async fn guests(self) -> Result<Vec<Guest>, Error> {
+        let guests: Vec<Guest> = self
+            .request(Method::GET, "/cluster/resources?type=vm", &[])
+            .await?;
+
+        Ok(guests.into_iter().filter(|guest| !guest.template).collect())
+    }
+
+    /// Lists the nodes that are members of the cluster, which is just the one for standalone
+    /// hosts.
+    pub async fn nodes(self) -> Result<Vec<ClusterNode>, Error> {
+        let status: Vec<ClusterStatus> = self.request(Method::GET, "/cluster/status", &[]).await?;
+
+        Ok(status
+            .into_iter()
+            .filter_map(|entry| match entry {
+                ClusterStatus::Node(node) => Some(node),
+                ClusterStatus::Other => None,
+            })
+            .collect())
+    }
+
+    pub async fn guest_status(self, guest: Guest) -> Result<GuestStatus, Error> {
+        let mut status: GuestStatus = self
+            .request(
+                Method::GET,
+                &format!("{}/status/current", guest.path()),
+                &[],
+            )
+            .await?;
+
+        if guest.kind == GuestKind::Qemu {
+            let config: VmConfig = self
+                .request(Method::GET, &format!("{}/config", guest.path()), &[])
+                .await?;
+            status.has_spice = matches!(config.display(), DisplayType::Spice);
+        }
+
+        Ok(status)
+    }
+
+    /// Opens the display of the VM with the best protocol it supports.
+    pub async fn connection_config(self, guest: Guest) -> Result<ConnectionConfig, Error> {
+        let config: VmConfig = self
+            .request(Method::GET, &format!("{}/config", guest.path()), &[])
+            .await?;
+
+        match config.display() {
+            DisplayType::Spice => self
+                .spice_config(guest)
+                .await
+                .map(|config| ConnectionConfig::Spice(Box::new(config))),
+            DisplayType::Vnc => self.vnc_config(guest).await.map(ConnectionConfig::Vnc),
+            DisplayType::Serial => Err(Error::Unsupported(
+                "Guest only has a serial console".to_owned(),
+            )),
+            DisplayType::None => Err(Error::Unsupported("Guest has no display".to_owned())),
+        }
+    }
+
+    async fn spice_config(&self, guest: Guest) -> Result<SpiceConfig, Error> {
+        // The proxy defaults to the name of the node, which isn't necessarily resolvable from here,
+        // so use whichever host answers instead
+        self.request_each_host(
+            Method::POST,
+            &format!("{}/spiceproxy", guest.path()),
+            |host| vec![("proxy", host.address().to_string())],
+        )
+        .await
+        .map(|(_, config)| config)
+    }
+
+    /// Opens a VNC display, whose port is opened on the node of the guest rather than the host
+    /// that answers, so it's requested through the host of that node.
+    async fn vnc_config(&self, guest: Guest) -> Result<VncConfig, Error> {
+        let path = format!("{}/vncproxy", guest.path());
+        let nodes = self.clone().nodes().await?;
+        let node = nodes
+            .iter()
+            .find(|node| node.name == guest.node)
+            .ok_or_else(|| {
+                Error::Unsupported(format!("Node {} isn't in the cluster", guest.node))
+            })?;
+
+        let client = Self {
+            hosts: self
+                .hosts
+                .iter()
+                .filter(|host| host.is_node(node))
+                .cloned()
+                .collect(),
+            auth: self.auth.clone(),
+        };
+        if !client.hosts.is_empty() {
+            let (host, mut config): (_, VncConfig) = client
+                .request_each_host(Method::POST, &path, |_| {
+                    vec![("generate-password", "1".to_owned())]
+                })
+                .await?;
+
+            config.host = host.address().to_string();
+            return Ok(config);
+        }
+
+        // Without a host for the node, the address it has within the cluster is all there is
+        let address = node.ip.clone().ok_or_else(|| {
+            Error::Unsupported(format!("Node {} has no known address", guest.node))
+        })?;
+        let mut config: VncConfig = self
+            .request(Method::POST, &path, &[("generate-password", "1")])
+            .await?;
+
+        config.host = address;
+        Ok(config)
+    }
+
+    /// Opens a terminal proxy to the container, and connects to it through the websocket of the
+    /// host that opened it.
+    pub async fn term_proxy(self, guest: Guest) -> Result<(TermProxy, Upgraded), Error> {
+        let (host, proxy): (_, TermProxy) = self
+            .request_each_host(
+                Method::POST,
+                &format!("{}/termproxy", guest.path()),
+                |_| vec![],
+            )
+            .await?;
+
+        let url = Url::parse_with_params(
+            &format!("https://{host}/api2/json{}/vncwebsocket", guest.path()),
+            &[
+                ("port", proxy.port.to_string().as_str()),
+                ("vncticket", proxy.ticket.expose()),
+            ],
+        )
+        .map_err(|err| Error::Parse(err.to_string()))?;
+
+        let mut key = [0; 16];
+        OsRng.fill_bytes(&mut key);
+        let mut request = http(host)?
+            .get(url)
+            .header(header::CONNECTION, "Upgrade")
+            .header(header::UPGRADE, "websocket")
+            .header(header::SEC_WEBSOCKET_VERSION, "13")
+            .header(header::SEC_WEBSOCKET_KEY, BASE64_STANDARD.encode(key))
+            .header(header::SEC_WEBSOCKET_PROTOCOL, "binary");
+        if let Some(auth) = &self.auth {
+            let (name, value) = auth.header();
+            request = request.header(name, value.expose());
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|err| Error::Unreachable(err.to_string()))?;
+        match response.status() {
+            StatusCode::SWITCHING_PROTOCOLS => {}
+            StatusCode::UNAUTHORIZED => return Err(Error::Unauthorized),
+            status => {
+                return Err(Error::Status(
+                    status.as_u16(),
+                    status.canonical_reason().unwrap_or_default().to_owned(),
+                ));
+            }
+        }
+
+        let websocket = response
+            .upgrade()
+            .await
+            .map_err(|err| Error::Unreachable(err.to_string()))?;
+        Ok((proxy, websocket))
+    }
+
+    /// Sends the request to a single host at a time until one answers, returning that host as well.
+    ///
+    /// Consoles have to be reached through the host that opened them, rather than any host.
+    async fn request_each_host<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        form: impl Fn(&Host) -> Vec<(&'static str, String)>,
+    ) -> Result<(&Host, T), Error> {
+        let mut error = Error::NoHosts;
+
+        for host in &self.hosts {
+            let client = Self {
+                hosts: vec![host.clone()],
+                auth: self.auth.clone(),
+            };
+            let form = form(host);
+            let form: Vec<(&str, &str)> = form
+                .iter()
+                .map(|(key, value)| (*key, value.as_str()))
+                .collect();
+
+            match client.request(method.clone(), path, &form).await {
+                Err(err @ (Error::Unreachable(_) | Error::Untrusted)) => error = err,
+                result => return result.map(|data| (host, data)),
+            }
+        }
+
+        Err(error)
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        form: &[(&str, &str)],
+    ) -> Result<T, Error> {
+        #[derive(Deserialize)]
+        struct Response<T> {
+            data: T,
+        }
+
+        let mut error = Error::NoHosts;
+
+        for host in &self.hosts {
+            // A host with an invalid fingerprint is skipped like an unreachable one, so it doesn't
+            // keep the other hosts from being tried
+            let http = match http(host) {
+                Ok(http) => http,
+                Err(err) => {
+                    error = err;
+                    continue;
+                }
+            };
+            let mut request =
+                http.request(method.clone(), format!("https://{host}/api2/json{path}"));
+
+            if let Some(auth) = &self.auth {
+                let (name, value) = auth.header();
+                request = request.header(name, value.expose());
+
+                if let Auth::Ticket(ticket) = auth
+                    && method != Method::GET
+                {
+                    request = request.header("CSRFPreventionToken", ticket.csrf.expose());
+                }
+            }
+
+            if !form.is_empty() {
+                request = request.form(form);
+            }
+
+            let response = match request.send().await {
+                Ok(response) => response,
+                Err(err) if is_untrusted(&err) => {
+                    error = Error::Untrusted;
+                    continue;
+                }
+                Err(err) if err.is_connect() || err.is_timeout() => {
+                    error = Error::Unreachable(err.to_string());
+                    continue;
+                }
+                Err(err) => return Err(Error::Unreachable(err.to_string())),
+            };
+
+            return match response.status() {
+                StatusCode::UNAUTHORIZED => Err(Error::Unauthorized),
+                status if !status.is_success() => Err(Error::Status(
+                    status.as_u16(),
+                    status.canonical_reason().unwrap_or_default().to_owned(),
+                )),
+                _ => response
+                    .json::<Response<T>>()
+                    .await
+                    .map(|response| response.data)
+                    .map_err(|err| Error::Parse(err.to_string())),
+            };
+        }
+
+        Err(error)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Error {
+    NoHosts,
+    Unreachable(String),
+    /// The certificate of the host doesn't match its fingerprint, or isn't signed by a trusted CA
+    /// when it has none.
+    Untrusted,
+    Unauthorized,
+    Status(u16, String),
+    Parse(String),
+    Unsupported(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoHosts => write!(f, "Cluster has no hosts"),
+            Self::Unreachable(err) => write!(f, "Cluster is unreachable: {err}"),
+            Self::Untrusted => write!(
+                f,
+                "Certificate isn't trusted, check the fingerprint of the host"
+            ),
+            Self::Unauthorized => write!(f, "Authentication failed"),
+            Self::Status(code, reason) => write!(f, "Request failed with {code} {reason}"),
+            Self::Parse(err) => write!(f, "Unexpected response: {err}"),
+            Self::Unsupported(reason) => reason.fmt(f),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub enum Auth {
-    ApiToken(String),
+    ApiToken(Secret),
     Ticket(Ticket),
 }
 
+impl Auth {
+    /// Header authenticating a request, though changes with a ticket need the CSRF token as well.
+    pub fn header(&self) -> (HeaderName, Secret) {
+        match self {
+            Self::ApiToken(token) => (
+                header::AUTHORIZATION,
+                Secret::new(format!("PVEAPIToken={}", token.expose())),
+            ),
+            Self::Ticket(ticket) => (
+                header::COOKIE,
+                Secret::new(format!("PVEAuthCookie={}", ticket.ticket.expose())),
+            ),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Ticket {
-    pub ticket: String,
+    pub ticket: Secret,
     #[serde(rename = "CSRFPreventionToken")]
-    pub csrf: String,
+    pub csrf: Secret,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Version {
+    pub version: String,
+}
+
+/// Everything a viewer needs to connect to the display of a VM.
+#[derive(Clone, Debug)]
+pub enum ConnectionConfig {
+    Spice(Box<SpiceConfig>),
+    Vnc(VncConfig),
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct SpiceConfig {
     pub host: String,
-    pub password: String,
+    pub password: Secret,
     pub proxy: String,
     #[serde(rename = "tls-port")]
     pub tls_port: u16,
     #[serde(rename = "type")]
     pub conn_type: String,
+    /// Certificate of the CA the host was signed with, in PEM format.
+    #[serde(default, deserialize_with = "escaped_newlines")]
+    pub ca: Option<String>,
+    #[serde(rename = "host-subject")]
+    pub host_subject: Option<String>,
+    /// Key combination sent as Ctrl+Alt+Del.
+    #[serde(rename = "secure-attention")]
+    pub secure_attention: Option<String>,
+    #[serde(rename = "release-cursor")]
+    pub release_cursor: Option<String>,
+    #[serde(rename = "toggle-fullscreen")]
+    pub toggle_fullscreen: Option<String>,
+    #[serde(rename = "delete-this-file")]
+    pub delete_this_file: Option<u8>,
+    pub title: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct VncConfig {
+    #[serde(skip)]
+    pub host: String,
+    #[serde(deserialize_with = "number")]
+    pub port: u16,
+    pub password: Secret,
+}
+
+/// Terminal of a container, relayed through a websocket.
+///
+/// Clients have to send `{user}:{ticket}\n` once connected before the terminal is relayed.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TermProxy {
+    #[serde(deserialize_with = "number")]
+    pub port: u16,
+    /// Ticket that lets the websocket in once, for this terminal only.
+    pub ticket: Secret,
+    pub user: String,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Guest {
+    #[serde(default)]
     pub name: String,
     pub vmid: u32,
     pub node: String,
     #[serde(rename = "type")]
     pub kind: GuestKind,
+    pub status: RunState,
+    /// Seconds since the guest was started.
+    #[serde(default)]
+    pub uptime: u64,
+    /// Fraction of the guest's CPUs in use.
+    #[serde(default)]
+    pub cpu: f64,
+    /// Memory in use in bytes.
+    #[serde(default)]
+    pub mem: u64,
+    #[serde(default)]
+    pub maxmem: u64,
+    /// Operation holding the guest's config lock, such as `backup` or `migrate`.
+    pub lock: Option<String>,
+    pub pool: Option<String>,
+    #[serde(default, deserialize_with = "tag_list")]
+    pub tags: Vec<String>,
+    #[serde(default, deserialize_with = "int_bool")]
+    pub template: bool,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl Guest {
+    /// API path of the guest, relative to the API root.
+    pub fn path(&self) -> String {
+        format!(
+            "/nodes/{}/{}/{}",
+            self.node,
+            self.kind.endpoint(),
+            self.vmid
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialOrd, Ord, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum GuestKind {
     #[serde(rename = "qemu")]
     Qemu,
@@ -42,6 +736,17 @@ pub enum GuestKind {
     Lxc,
 }
 
+impl GuestKind {
+    pub const ALL: [Self; 2] = [Self::Qemu, Self::Lxc];
+
+    const fn endpoint(self) -> &'static str {
+        match self {
+            Self::Qemu => "qemu",
+            Self::Lxc => "lxc",
+        }
+    }
+}
+
 impl Display for GuestKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self {
@@ -50,3 +755,270 @@ impl Display for GuestKind {
         }
     }
 }
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GuestStatus {
+    pub status: RunState,
+    /// More detailed state reported by QEMU, missing for containers.
+    pub qmpstatus: Option<RunState>,
+    /// Whether the SPICE display of the VM is up.
+    #[serde(default, deserialize_with = "int_bool")]
+    spice: bool,
+    /// Whether the VM is configured with a SPICE display, which has to be up before connecting.
+    #[serde(skip)]
+    has_spice: bool,
+}
+
+impl GuestStatus {
+    /// Whether the guest is running, QEMU isn't paused, and its SPICE display is up if it has one,
+    /// so its console can be connected to.
+    pub fn ready(&self) -> bool {
+        self.status == RunState::Running
+            && self
+                .qmpstatus
+                .is_none_or(|status| status == RunState::Running)
+            && (self.spice || !self.has_spice)
+    }
+}
+
+/// Entry of the cluster status, which describes either the cluster itself or one of its nodes.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClusterStatus {
+    Node(ClusterNode),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ClusterNode {
+    pub name: String,
+    /// Address the node uses within the cluster, missing if it isn't known.
+    pub ip: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct VmConfig {
+    /// Display adapter and its options, such as `qxl,memory=32` or `type=qxl,memory=32`.
+    vga: Option<String>,
+}
+
+impl VmConfig {
+    fn display(&self) -> DisplayType {
+        // The type is either given as `type=` or as the only value without a key
+        let vga = self
+            .vga
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .find_map(|field| match field.split_once('=') {
+                Some(("type", vga)) => Some(vga),
+                Some(_) => None,
+                None => Some(field),
+            })
+            .unwrap_or_default();
+
+        match vga {
+            vga if vga.starts_with("qxl") => DisplayType::Spice,
+            vga if vga.starts_with("serial") => DisplayType::Serial,
+            "none" => DisplayType::None,
+            // Anything else, including the default, is a regular VGA display
+            _ => DisplayType::Vnc,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum DisplayType {
+    Spice,
+    Vnc,
+    Serial,
+    None,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunState {
+    Running,
+    Stopped,
+    Paused,
+    Suspended,
+    Prelaunch,
+    #[serde(other)]
+    Unknown,
+}
+
+impl Display for RunState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Running => write!(f, "running"),
+            Self::Stopped => write!(f, "stopped"),
+            Self::Paused => write!(f, "paused"),
+            Self::Suspended => write!(f, "suspended"),
+            Self::Prelaunch => write!(f, "starting"),
+            Self::Unknown => write!(f, "in an unknown state"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerAction {
+    Start,
+    Shutdown,
+    Reboot,
+    Stop,
+    Suspend,
+    Resume,
+}
+
+impl PowerAction {
+    pub const ALL: [Self; 6] = [
+        Self::Start,
+        Self::Shutdown,
+        Self::Reboot,
+        Self::Stop,
+        Self::Suspend,
+        Self::Resume,
+    ];
+
+    const fn endpoint(self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Shutdown => "shutdown",
+            Self::Reboot => "reboot",
+            Self::Stop => "stop",
+            Self::Suspend => "suspend",
+            Self::Resume => "resume",
+        }
+    }
+
+    /// Describes the action while its task is still running.
+    pub const fn progress(self) -> &'static str {
+        match self {
+            Self::Start => "Starting...",
+            Self::Shutdown => "Shutting down...",
+            Self::Reboot => "Rebooting...",
+            Self::Stop => "Stopping...",
+            Self::Suspend => "Suspending...",
+            Self::Resume => "Resuming...",
+        }
+    }
+}
+
+impl Display for PowerAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Start => write!(f, "Start"),
+            Self::Shutdown => write!(f, "Shutdown"),
+            Self::Reboot => write!(f, "Reboot"),
+            Self::Stop => write!(f, "Stop"),
+            Self::Suspend => write!(f, "Suspend"),
+            Self::Resume => write!(f, "Resume"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TaskStatus {
+    pub status: TaskState,
+    /// Either `OK` or the error the task failed with, only present once the task has stopped.
+    pub exitstatus: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    Running,
+    Stopped,
+}
+
+/// Proxmox encodes booleans as `0` and `1`.
+fn int_bool<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    Ok(u8::deserialize(deserializer)? != 0)
+}
+
+/// The CA certificate is sent with its newlines escaped already, ready to be pasted into a .vv file.
+fn escaped_newlines<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.map(|ca| ca.replace("\\n", "\n")))
+}
+
+/// Some numbers are sent as strings, depending on how they were set on the server.
+fn number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Number {
+        Int(u16),
+        Str(String),
+    }
+
+    match Number::deserialize(deserializer)? {
+        Number::Int(number) => Ok(number),
+        Number::Str(number) => number.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+/// Tags are sent as a single string, separated by `;`, though `,` and spaces are accepted as well.
+fn tag_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Ok(String::deserialize(deserializer)?
+        .split([';', ',', ' '])
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_owned)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(vga: Option<&str>) -> DisplayType {
+        VmConfig {
+            vga: vga.map(str::to_owned),
+        }
+        .display()
+    }
+
+    #[test]
+    fn display_follows_the_vga_type() {
+        assert_eq!(display(Some("qxl")), DisplayType::Spice);
+        assert_eq!(display(Some("qxl2,memory=32")), DisplayType::Spice);
+        assert_eq!(display(Some("type=qxl,memory=32")), DisplayType::Spice);
+        assert_eq!(display(Some("memory=32,type=qxl")), DisplayType::Spice);
+        assert_eq!(display(Some("std")), DisplayType::Vnc);
+        assert_eq!(display(Some("serial0")), DisplayType::Serial);
+        assert_eq!(display(Some("type=serial0")), DisplayType::Serial);
+        assert_eq!(display(Some("none")), DisplayType::None);
+        assert_eq!(display(Some("type=none")), DisplayType::None);
+        assert_eq!(display(None), DisplayType::Vnc);
+    }
+
+    const FINGERPRINT: &str = "AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89:\
+        AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89";
+
+    #[test]
+    fn fingerprint_round_trips() {
+        assert_eq!(
+            parse_fingerprint(FINGERPRINT).map(|bytes| format_fingerprint(&bytes)),
+            Some(FINGERPRINT.to_owned())
+        );
+    }
+
+    #[test]
+    fn fingerprint_ignores_case_and_separators() {
+        let fingerprint = FINGERPRINT.to_lowercase().replace(':', " ");
+
+        assert_eq!(
+            parse_fingerprint(&fingerprint),
+            parse_fingerprint(FINGERPRINT)
+        );
+        assert!(parse_fingerprint(FINGERPRINT).is_some());
+    }
+
+    #[test]
+    fn fingerprint_rejects_other_lengths_and_digits() {
+        assert_eq!(parse_fingerprint("AB:CD"), None);
+        assert_eq!(parse_fingerprint(&FINGERPRINT.replace("AB", "GH")), None);
+        assert_eq!(parse_fingerprint(&format!("{FINGERPRINT}:AB")), None);
+    }
+}
@@ -0,0 +1,336 @@
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{self, Debug, Display},
+    fs::{self, File},
+    io::{self, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Passphrase the secrets are encrypted with, instead of the ID of the machine.
+pub const PASSPHRASE_VAR: &str = "ANTECHAMBER_PASSPHRASE";
+
+const SECRETS_FILE_NAME: &str = "secrets.bin";
+const MACHINE_ID_PATHS: [&str; 2] = ["/etc/machine-id", "/var/lib/dbus/machine-id"];
+/// Prefix of the values in the config that refer to a secret by ID.
+const REFERENCE_PREFIX: &str = "secret:";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// Credential that `Debug` and `Display` don't show, and that is wiped from memory when dropped.
+#[derive(Clone, Default, PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub const fn new(secret: String) -> Self {
+        Self(secret)
+    }
+
+    /// The secret itself, for when it has to be sent somewhere or typed in.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(secret: &str) -> Self {
+        Self(secret.to_owned())
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(<redacted>)")
+    }
+}
+
+impl Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// No passphrase is set and the machine has no ID to use instead.
+    NoKey,
+    /// The file was encrypted with another key, or was tampered with.
+    Decrypt,
+    Format(serde_yaml::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "Can't access the secrets: {err}"),
+            Self::NoKey => write!(f, "Set {PASSPHRASE_VAR}, the machine has no ID to use"),
+            Self::Decrypt => write!(f, "Can't decrypt the secrets"),
+            Self::Format(err) => write!(f, "Invalid secrets: {err}"),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Secrets kept out of the config, in a file that only its owner can read, encrypted with a key
+/// derived from [`PASSPHRASE_VAR`] or the ID of the machine.
+#[derive(Clone)]
+pub struct SecretStore {
+    path: PathBuf,
+    /// Key the file is encrypted with, and the salt it was derived with, once it's known.
+    key: Option<([u8; SALT_LEN], Zeroizing<[u8; 32]>)>,
+    secrets: BTreeMap<String, Secret>,
+    /// Whether the secrets changed since they were read or written.
+    changed: bool,
+}
+
+impl Debug for SecretStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretStore")
+            .field("path", &self.path)
+            .field("ids", &self.secrets.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl SecretStore {
+    /// Reads the secrets in `config_dir`, which are empty if there's no file yet.
+    pub fn open(config_dir: &Path) -> Result<Self, Error> {
+        let path = config_dir.join(SECRETS_FILE_NAME);
+        let mut store = Self {
+            path,
+            key: None,
+            secrets: BTreeMap::new(),
+            changed: false,
+        };
+
+        let file = match fs::read(&store.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(store),
+            Err(err) => return Err(err.into()),
+        };
+
+        if file.len() < SALT_LEN + NONCE_LEN {
+            return Err(Error::Decrypt);
+        }
+        let (salt, file) = file.split_at(SALT_LEN);
+        let (nonce, ciphertext) = file.split_at(NONCE_LEN);
+        let salt: [u8; SALT_LEN] = salt.try_into().map_err(|_| Error::Decrypt)?;
+        let key = derive_key(&salt)?;
+
+        let mut plaintext = XChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Error::Decrypt)?;
+        let secrets = serde_yaml::from_slice(&plaintext).map_err(Error::Format);
+        plaintext.zeroize();
+        store.secrets = secrets?;
+        store.key = Some((salt, key));
+
+        Ok(store)
+    }
+
+    /// Looks up the secret a value of the config refers to.
+    pub fn get(&self, reference: &str) -> Option<&Secret> {
+        reference
+            .strip_prefix(REFERENCE_PREFIX)
+            .and_then(|id| self.secrets.get(id))
+    }
+
+    /// Stores `secret`, returning the value the config refers to it by.
+    ///
+    /// Secrets that are already stored keep their ID.
+    pub fn insert(&mut self, secret: Secret) -> String {
+        let id = if let Some((id, _)) = self.secrets.iter().find(|(_, other)| **other == secret) {
+            id.clone()
+        } else {
+            let id = format!("{:016x}", OsRng.next_u64());
+
+            self.secrets.insert(id.clone(), secret);
+            self.changed = true;
+            id
+        };
+
+        format!("{REFERENCE_PREFIX}{id}")
+    }
+
+    /// Forgets every secret that isn't referred to by one of `references`.
+    pub fn retain(&mut self, references: &[String]) {
+        let before = self.secrets.len();
+        self.secrets.retain(|id, _| {
+            references
+                .iter()
+                .any(|reference| reference.strip_prefix(REFERENCE_PREFIX) == Some(id))
+        });
+        self.changed |= self.secrets.len() != before;
+    }
+
+    /// Writes the secrets if they changed.
+    pub fn save(&mut self) -> Result<(), Error> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        let (salt, key) = if let Some(key) = &self.key {
+            key
+        } else {
+            let mut salt = [0; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            self.key.insert((salt, derive_key(&salt)?))
+        };
+
+        let mut plaintext = serde_yaml::to_string(&self.secrets).map_err(Error::Format)?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = XChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+            .encrypt(&nonce, plaintext.as_bytes())
+            .expect("Encryption only fails for huge inputs");
+        plaintext.zeroize();
+
+        let mut file = create_private(&self.path)?;
+        file.write_all(salt)?;
+        file.write_all(&nonce)?;
+        file.write_all(&ciphertext)?;
+
+        self.changed = false;
+        Ok(())
+    }
+}
+
+/// Whether a value of the config refers to a secret, rather than being one.
+pub fn is_reference(value: &str) -> bool {
+    value.starts_with(REFERENCE_PREFIX)
+}
+
+/// Creates or truncates a file that only its owner can read and write.
+pub fn create_private(path: &Path) -> io::Result<File> {
+    let file = File::options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // The mode only applies to new files
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+
+    Ok(file)
+}
+
+fn derive_key(salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, Error> {
+    let secret = env::var(PASSPHRASE_VAR)
+        .ok()
+        .filter(|passphrase| !passphrase.is_empty())
+        .or_else(|| {
+            MACHINE_ID_PATHS
+                .iter()
+                .filter_map(|path| fs::read_to_string(path).ok())
+                .map(|id| id.trim().to_owned())
+                .find(|id| !id.is_empty())
+        })
+        .map(Secret::new)
+        .ok_or(Error::NoKey)?;
+
+    let mut key = Zeroizing::new([0; 32]);
+    Argon2::default()
+        .hash_password_into(secret.expose().as_bytes(), salt, key.as_mut())
+        .map_err(|_| Error::NoKey)?;
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory of its own for each test, as the store only ever uses the one file.
+    fn config_dir() -> io::Result<PathBuf> {
+        let dir = env::temp_dir().join(format!("secrets-test-{:016x}", OsRng.next_u64()));
+        fs::create_dir(&dir)?;
+
+        Ok(dir)
+    }
+
+    #[test]
+    fn store_round_trips_through_its_file() -> Result<(), Error> {
+        // The key has to come from the machine, as tests can't safely set the passphrase
+        if matches!(derive_key(&[0; SALT_LEN]), Err(Error::NoKey)) {
+            return Ok(());
+        }
+        let dir = config_dir()?;
+
+        let mut store = SecretStore::open(&dir)?;
+        let password = store.insert(Secret::from("hunter2"));
+        let token = store.insert(Secret::from("token"));
+        assert_eq!(store.insert(Secret::from("hunter2")), password);
+        store.retain(std::slice::from_ref(&password));
+        store.save()?;
+
+        let mode = fs::metadata(dir.join(SECRETS_FILE_NAME))?
+            .permissions()
+            .mode();
+        let store = SecretStore::open(&dir)?;
+        fs::remove_dir_all(&dir)?;
+
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(is_reference(&password));
+        assert_eq!(store.get(&password).map(Secret::expose), Some("hunter2"));
+        assert_eq!(store.get(&token), None);
+        Ok(())
+    }
+
+    #[test]
+    fn store_refuses_tampered_files() -> Result<(), Error> {
+        if matches!(derive_key(&[0; SALT_LEN]), Err(Error::NoKey)) {
+            return Ok(());
+        }
+        let dir = config_dir()?;
+
+        let mut store = SecretStore::open(&dir)?;
+        store.insert(Secret::from("hunter2"));
+        store.save()?;
+        let path = dir.join(SECRETS_FILE_NAME);
+        let mut file = fs::read(&path)?;
+        if let Some(byte) = file.last_mut() {
+            *byte ^= 1;
+        }
+        fs::write(&path, file)?;
+
+        let result = SecretStore::open(&dir);
+        fs::remove_dir_all(&dir)?;
+
+        assert!(matches!(result, Err(Error::Decrypt)));
+        Ok(())
+    }
+
+    #[test]
+    fn secret_is_redacted() {
+        let secret = Secret::from("hunter2");
+
+        assert_eq!(
+            format!("{secret} {secret:?}"),
+            "<redacted> Secret(<redacted>)"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use crate::config::ConfigManager;
+use std::{
+    backtrace::Backtrace,
+    collections::VecDeque,
+    env,
+    fs::{self, File},
+    io::Write,
+    panic,
+    process::{self, Command},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Runs the app as a child process that is restarted whenever it crashes.
+pub const FLAG: &str = "--supervise";
+
+const CRASH_LOG_FILE_NAME: &str = "crash.log";
+/// More restarts than this within [`RESTART_WINDOW`] are delayed until the oldest one has expired.
+const MAX_RESTARTS: usize = 5;
+const RESTART_WINDOW: Duration = Duration::from_mins(1);
+
+/// Restarts the app after every crash until it exits cleanly, which only happens when it is quit
+/// from admin mode.
+pub fn supervise() -> ! {
+    let exe = env::current_exe().expect("Supervisor needs to know its own executable");
+    let args: Vec<String> = env::args().skip(1).filter(|arg| arg != FLAG).collect();
+    let mut restarts = VecDeque::with_capacity(MAX_RESTARTS);
+
+    loop {
+        match Command::new(&exe).args(&args).status() {
+            Ok(status) if status.success() => process::exit(0),
+            Ok(status) => eprintln!("Antechamber exited with {status}, restarting"),
+            Err(err) => eprintln!("Failed to start Antechamber: {err}"),
+        }
+
+        while restarts
+            .front()
+            .is_some_and(|restart: &Instant| restart.elapsed() > RESTART_WINDOW)
+        {
+            restarts.pop_front();
+        }
+
+        if restarts.len() >= MAX_RESTARTS
+            && let Some(oldest) = restarts.pop_front()
+        {
+            thread::sleep(RESTART_WINDOW.saturating_sub(oldest.elapsed()));
+        }
+
+        restarts.push_back(Instant::now());
+    }
+}
+
+/// Writes panics to the crash log in the config directory, in addition to the default output.
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        // Nothing sensible can be done if this fails while already panicking
+        let _ = write_crash_log(&info.to_string());
+        default_hook(info);
+    }));
+}
+
+fn write_crash_log(message: &str) -> Option<()> {
+    let config_dir = ConfigManager::config_dir()?;
+    fs::create_dir_all(&config_dir).ok()?;
+
+    let mut file = File::options()
+        .create(true)
+        .append(true)
+        .open(config_dir.join(CRASH_LOG_FILE_NAME))
+        .ok()?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let thread = thread::current();
+
+    writeln!(
+        file,
+        "[{timestamp}] thread '{}' {message}\n{}\n",
+        thread.name().unwrap_or("<unnamed>"),
+        Backtrace::force_capture()
+    )
+    .ok()
+}
@@ -0,0 +1,218 @@
+use crate::{
+    proxmox::{Client, Guest},
+    secrets::Secret,
+    viewer,
+};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use iced::{
+    futures::{channel::mpsc, future, lock::Mutex, SinkExt, Stream},
+    stream,
+};
+use std::{fs, io, pin::pin, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::UnixListener,
+    time,
+};
+
+const SOCKET_NAME: &str = "terminal.sock";
+/// Time the terminal command has to connect to the socket.
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Time without input after which the proxy is pinged, so it doesn't close the connection.
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// Size the terminal is set to, since the command can't tell its size through the socket.
+const COLUMNS: u16 = 80;
+const ROWS: u16 = 24;
+/// Largest frame accepted from the websocket, which the proxy keeps far below.
+const MAX_FRAME_LEN: u64 = 1 << 20;
+
+/// Lengths of frames that are followed by their actual length in 2 or 8 bytes.
+const LEN_U16: u8 = 126;
+const LEN_U64: u8 = 127;
+const MASKED: u8 = 0x80;
+
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+/// Opens the terminal of the container and relays it to `command` through a Unix socket, so the
+/// command is never handed any credentials.
+///
+/// Yields once the command is started or failed to be, and again if the relay fails after that.
+pub fn open(
+    client: Client,
+    guest: Guest,
+    command: Vec<String>,
+) -> impl Stream<Item = Result<(), String>> {
+    stream::channel(
+        1,
+        async move |mut output: mpsc::Sender<Result<(), String>>| {
+            if let Err(err) = relay(client, guest, &command, &mut output).await {
+                let _ = output.send(Err(err)).await;
+            }
+        },
+    )
+}
+
+async fn relay(
+    client: Client,
+    guest: Guest,
+    command: &[String],
+    output: &mut mpsc::Sender<Result<(), String>>,
+) -> Result<(), String> {
+    let (proxy, websocket) = client
+        .term_proxy(guest.clone())
+        .await
+        .map_err(|err| err.to_string())?;
+    let (mut reader, mut writer) = tokio::io::split(websocket);
+
+    // The proxy only relays the terminal once it's sent the ticket
+    let login = Secret::new(format!("{}:{}\n", proxy.user, proxy.ticket.expose()));
+    write_frame(&mut writer, OP_BINARY, login.expose().as_bytes())
+        .await
+        .map_err(|err| format!("Terminal proxy is unreachable: {err}"))?;
+    let writer = Mutex::new(writer);
+    match read_message(&mut reader, &writer).await {
+        Ok(Some(reply)) if reply.starts_with(b"OK") => {}
+        _ => return Err("Terminal proxy refused the ticket".to_owned()),
+    }
+
+    let dir = viewer::create_private_dir(guest.vmid)
+        .map_err(|err| format!("Failed to start terminal: {err}"))?;
+    let socket = dir.join(SOCKET_NAME);
+    let listener = match UnixListener::bind(&socket) {
+        Ok(listener) => listener,
+        Err(err) => {
+            let _ = fs::remove_dir_all(&dir);
+            return Err(format!("Failed to start terminal: {err}"));
+        }
+    };
+    viewer::launch_terminal(dir, &socket, &guest, command)
+        .map_err(|err| format!("Failed to start terminal: {err}"))?;
+    let _ = output.send(Ok(())).await;
+
+    let (local, _) = time::timeout(ACCEPT_TIMEOUT, listener.accept())
+        .await
+        .map_err(|_| "Terminal didn't connect to its socket".to_owned())?
+        .map_err(|err| format!("Terminal didn't connect to its socket: {err}"))?;
+    drop(listener);
+    let (mut local_reader, mut local_writer) = local.into_split();
+
+    let resize = format!("1:{COLUMNS}:{ROWS}:");
+    write_frame(&mut *writer.lock().await, OP_BINARY, resize.as_bytes())
+        .await
+        .map_err(|err| format!("Terminal disconnected: {err}"))?;
+
+    let input = async {
+        let mut buf = vec![0; 4096];
+
+        loop {
+            let message = match time::timeout(PING_INTERVAL, local_reader.read(&mut buf)).await {
+                Err(_) => b"2".to_vec(),
+                Ok(Ok(0)) => return Ok(()),
+                Ok(Ok(len)) => {
+                    let mut message = format!("0:{len}:").into_bytes();
+                    message.extend_from_slice(&buf[..len]);
+                    message
+                }
+                Ok(Err(err)) => return Err(err),
+            };
+
+            write_frame(&mut *writer.lock().await, OP_BINARY, &message).await?;
+        }
+    };
+    let output = async {
+        while let Some(data) = read_message(&mut reader, &writer).await? {
+            local_writer.write_all(&data).await?;
+        }
+
+        Ok::<_, io::Error>(())
+    };
+
+    let result = future::select(pin!(input), pin!(output))
+        .await
+        .factor_first()
+        .0;
+    let _ = write_frame(&mut *writer.lock().await, OP_CLOSE, &[]).await;
+
+    result.map_err(|err| format!("Terminal disconnected: {err}"))
+}
+
+/// Reads the payload of the next message of the websocket, answering pings on the way, or `None`
+/// once it's closed.
+async fn read_message(
+    reader: &mut (impl AsyncRead + Unpin),
+    writer: &Mutex<impl AsyncWrite + Unpin>,
+) -> io::Result<Option<Vec<u8>>> {
+    loop {
+        let mut head = [0; 2];
+        reader.read_exact(&mut head).await?;
+
+        let opcode = head[0] & 0x0f;
+        let masked = head[1] & MASKED != 0;
+        let len = match head[1] & 0x7f {
+            LEN_U16 => u64::from(reader.read_u16().await?),
+            LEN_U64 => reader.read_u64().await?,
+            len => u64::from(len),
+        };
+        if len > MAX_FRAME_LEN {
+            return Err(io::Error::other("Frame is too large"));
+        }
+
+        let mut mask = [0; 4];
+        if masked {
+            reader.read_exact(&mut mask).await?;
+        }
+        let mut payload = vec![0; usize::try_from(len).map_err(io::Error::other)?];
+        reader.read_exact(&mut payload).await?;
+        if masked {
+            for (idx, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[idx % 4];
+            }
+        }
+
+        match opcode {
+            OP_CLOSE => return Ok(None),
+            OP_PING => write_frame(&mut *writer.lock().await, OP_PONG, &payload).await?,
+            OP_PONG => {}
+            // Continued frames are part of the byte stream of the terminal as well
+            _ => return Ok(Some(payload)),
+        }
+    }
+}
+
+/// Sends a single frame, masked as frames of clients have to be.
+async fn write_frame(
+    writer: &mut (impl AsyncWrite + Unpin),
+    opcode: u8,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 14);
+    frame.push(0x80 | opcode);
+
+    match (u8::try_from(payload.len()), u16::try_from(payload.len())) {
+        (Ok(len), _) if len < LEN_U16 => frame.push(MASKED | len),
+        (_, Ok(len)) => {
+            frame.push(MASKED | LEN_U16);
+            frame.extend_from_slice(&len.to_be_bytes());
+        }
+        _ => {
+            frame.push(MASKED | LEN_U64);
+            frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+        }
+    }
+
+    let mut mask = [0; 4];
+    OsRng.fill_bytes(&mut mask);
+    frame.extend_from_slice(&mask);
+    frame.extend(
+        payload
+            .iter()
+            .enumerate()
+            .map(|(idx, byte)| byte ^ mask[idx % 4]),
+    );
+
+    writer.write_all(&frame).await?;
+    writer.flush().await
+}
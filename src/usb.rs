@@ -0,0 +1,211 @@
+use crate::config::UsbPolicy;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    fs,
+    path::Path,
+};
+
+const SYSFS_DEVICES: &str = "/sys/bus/usb/devices";
+const HUB_CLASS: u8 = 0x09;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UsbDevice {
+    pub vendor: u16,
+    pub product: u16,
+    pub class: u8,
+    /// Manufacturer and product names reported by the device, if any.
+    pub name: String,
+}
+
+impl Display for UsbDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let id = format!("{:04x}:{:04x}", self.vendor, self.product);
+
+        if self.name.is_empty() {
+            id.fmt(f)
+        } else {
+            write!(f, "{} ({id})", self.name)
+        }
+    }
+}
+
+/// Lists the USB devices plugged into the client, leaving out hubs.
+pub fn devices() -> Vec<UsbDevice> {
+    let Ok(entries) = fs::read_dir(SYSFS_DEVICES) else {
+        return vec![];
+    };
+
+    let mut devices: Vec<UsbDevice> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| device(&entry.path()))
+        .filter(|device| device.class != HUB_CLASS)
+        .collect();
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+
+    devices
+}
+
+fn device(path: &Path) -> Option<UsbDevice> {
+    // Interfaces are listed next to devices, but only devices have IDs
+    let vendor = u16::from_str_radix(&attribute(path, "idVendor")?, 16).ok()?;
+    let product = u16::from_str_radix(&attribute(path, "idProduct")?, 16).ok()?;
+    let class = match u8::from_str_radix(&attribute(path, "bDeviceClass")?, 16).ok()? {
+        // The class is given by each interface instead, so go by the first one
+        0 => interface_class(path).unwrap_or_default(),
+        class => class,
+    };
+    let name = [attribute(path, "manufacturer"), attribute(path, "product")]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Some(UsbDevice {
+        vendor,
+        product,
+        class,
+        name,
+    })
+}
+
+fn interface_class(path: &Path) -> Option<u8> {
+    let device = path.file_name()?.to_str()?;
+    let interface = path.with_file_name(format!("{device}:1.0"));
+
+    u8::from_str_radix(&attribute(&interface, "bInterfaceClass")?, 16).ok()
+}
+
+fn attribute(path: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(path.join(name))
+        .ok()
+        .map(|value| value.trim().to_owned())
+}
+
+/// Options of the .vv file limiting redirection to the devices `policy` allows, and redirecting
+/// the `picked` devices as soon as the viewer is connected if they were picked.
+///
+/// The filter is always set, so the viewer doesn't redirect other devices when sharing them
+/// automatically.
+pub fn vv_options(policy: &UsbPolicy, picked: Option<&[UsbDevice]>) -> BTreeMap<String, String> {
+    // The first rule a device matches decides, and the denied ones win over everything else
+    let denied = policy
+        .deny
+        .iter()
+        .map(|rule| filter_rule(rule.class, rule.vendor, rule.product, false));
+
+    let allowed: Vec<String> = match picked {
+        Some(devices) => devices
+            .iter()
+            .map(|device| filter_rule(None, Some(device.vendor), Some(device.product), true))
+            .chain([filter_rule(None, None, None, false)])
+            .collect(),
+        None if policy.allow.is_empty() => vec![filter_rule(None, None, None, true)],
+        None => policy
+            .allow
+            .iter()
+            .map(|rule| filter_rule(rule.class, rule.vendor, rule.product, true))
+            .chain([filter_rule(None, None, None, false)])
+            .collect(),
+    };
+
+    let mut options = BTreeMap::from([(
+        "usb-filter".to_owned(),
+        denied.chain(allowed).collect::<Vec<_>>().join("|"),
+    )]);
+    if picked.is_some_and(|devices| !devices.is_empty()) {
+        options.insert("enable-usbredir".to_owned(), "1".to_owned());
+        options.insert("enable-usb-autoshare".to_owned(), "1".to_owned());
+    }
+
+    options
+}
+
+/// Rule of a `usb-filter`, which is `class,vendor,product,version,allow` with `-1` matching
+/// anything.
+fn filter_rule(
+    class: Option<u8>,
+    vendor: Option<u16>,
+    product: Option<u16>,
+    allow: bool,
+) -> String {
+    let class = class.map_or_else(|| "-1".to_owned(), |class| format!("0x{class:02x}"));
+    let vendor = vendor.map_or_else(|| "-1".to_owned(), |vendor| format!("0x{vendor:04x}"));
+    let product = product.map_or_else(|| "-1".to_owned(), |product| format!("0x{product:04x}"));
+
+    format!("{class},{vendor},{product},-1,{}", u8::from(allow))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::UsbRule;
+
+    fn policy() -> UsbPolicy {
+        UsbPolicy {
+            picker: true,
+            allow: vec![UsbRule {
+                class: Some(0x08),
+                ..UsbRule::default()
+            }],
+            deny: vec![UsbRule {
+                vendor: Some(0x1050),
+                ..UsbRule::default()
+            }],
+        }
+    }
+
+    fn filter(options: &BTreeMap<String, String>) -> Option<&str> {
+        options.get("usb-filter").map(String::as_str)
+    }
+
+    #[test]
+    fn filter_denies_before_allowing() {
+        let options = vv_options(&policy(), None);
+
+        assert_eq!(
+            filter(&options),
+            Some("-1,0x1050,-1,-1,0|0x08,-1,-1,-1,1|-1,-1,-1,-1,0")
+        );
+        assert!(!options.contains_key("enable-usbredir"));
+    }
+
+    #[test]
+    fn filter_allows_everything_without_allow_rules() {
+        let options = vv_options(&UsbPolicy::default(), None);
+
+        assert_eq!(filter(&options), Some("-1,-1,-1,-1,1"));
+    }
+
+    #[test]
+    fn filter_allows_only_picked_devices() {
+        let picked = [UsbDevice {
+            vendor: 0x0781,
+            product: 0x5567,
+            class: 0x08,
+            name: String::new(),
+        }];
+        let options = vv_options(&policy(), Some(&picked));
+
+        assert_eq!(
+            filter(&options),
+            Some("-1,0x1050,-1,-1,0|-1,0x0781,0x5567,-1,1|-1,-1,-1,-1,0")
+        );
+        assert_eq!(
+            options.get("enable-usb-autoshare").map(String::as_str),
+            Some("1")
+        );
+        assert_eq!(
+            options.get("enable-usbredir").map(String::as_str),
+            Some("1")
+        );
+    }
+
+    #[test]
+    fn nothing_is_shared_when_none_were_picked() {
+        let options = vv_options(&policy(), Some(&[]));
+
+        assert_eq!(filter(&options), Some("-1,0x1050,-1,-1,0|-1,-1,-1,-1,0"));
+        assert!(!options.contains_key("enable-usb-autoshare"));
+    }
+}
@@ -0,0 +1,311 @@
+use crate::{
+    config::{Audio, ViewerProfile},
+    proxmox::{ConnectionConfig, Guest},
+    secrets::Secret,
+    NAME_LOWER,
+};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{self, DirBuilder, File},
+    io::{self, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+};
+
+const VIEWER: &str = "remote-viewer";
+/// Terminal command used unless one is configured, which connects xterm to the socket the console
+/// is relayed to.
+const TERMINAL: [&str; 7] = [
+    "xterm",
+    "-T",
+    "{name}",
+    "-e",
+    "socat",
+    "-,raw,echo=0",
+    "UNIX-CONNECT:{socket}",
+];
+/// Group of virt-viewer's settings that applies to guests without a group of their own.
+const FALLBACK_GROUP: &str = "fallback";
+
+/// Starts the viewer for `config` without waiting for it to exit.
+///
+/// `overrides` replace the options of the .vv file that Proxmox and the profile set, or add to them.
+pub fn launch(
+    config: &ConnectionConfig,
+    vmid: u32,
+    profile: &ViewerProfile,
+    overrides: &BTreeMap<String, String>,
+) -> io::Result<()> {
+    let mut options = options(config);
+    options.extend(profile_options(profile));
+    options.extend(
+        overrides
+            .iter()
+            .map(|(key, value)| (key.clone(), Secret::from(value.as_str()))),
+    );
+    let (path, file) = create_private(vmid)?;
+
+    // The file holds the password, so it mustn't be left behind when the viewer doesn't start
+    let result = write_and_spawn(&path, file, options, vmid, profile);
+    if result.is_err() {
+        let _ = fs::remove_file(&path);
+    }
+
+    result
+}
+
+/// Writes the .vv file at `path` and starts the viewer with it.
+fn write_and_spawn(
+    path: &Path,
+    mut file: File,
+    options: BTreeMap<String, Secret>,
+    vmid: u32,
+    profile: &ViewerProfile,
+) -> io::Result<()> {
+    writeln!(file, "[virt-viewer]")?;
+    for (key, value) in options {
+        writeln!(file, "{key}={}", escape(value.expose()).expose())?;
+    }
+    drop(file);
+
+    let mut command = Command::new(profile.binary.as_deref().unwrap_or(VIEWER));
+    command.args(&profile.args).arg(path);
+
+    let Some(mapping) = &profile.monitor_mapping else {
+        return spawn(&mut command, None);
+    };
+
+    // The viewer only reads the mapping from its settings, so it gets settings of its own
+    let config_dir = create_private_dir(vmid)?;
+    if let Err(err) = write_settings(&config_dir, mapping) {
+        let _ = fs::remove_dir_all(&config_dir);
+        return Err(err);
+    }
+
+    spawn(
+        command.env("XDG_CONFIG_HOME", &config_dir),
+        Some(config_dir),
+    )
+}
+
+/// Random path in a directory that only this user can access, for files only the viewer reads.
+fn private_path(vmid: u32, extension: &str) -> PathBuf {
+    // Unlike the temporary directory, the runtime directory isn't shared with other users
+    let dir = env::var_os("XDG_RUNTIME_DIR").map_or_else(env::temp_dir, PathBuf::from);
+    dir.join(format!(
+        "{NAME_LOWER}-{vmid}-{:016x}{extension}",
+        OsRng.next_u64()
+    ))
+}
+
+/// Creates a new directory that only this user can access, for files that the viewer or terminal
+/// reads.
+///
+/// It mustn't exist yet for the same reason as the .vv file.
+pub fn create_private_dir(vmid: u32) -> io::Result<PathBuf> {
+    let dir = private_path(vmid, "");
+    DirBuilder::new().mode(0o700).create(&dir)?;
+
+    Ok(dir)
+}
+
+/// Creates a new .vv file that only this user can read, since it holds the connection password.
+///
+/// The name is random and the file mustn't exist yet, so other users can't prepare a file or
+/// symlink to read the password from.
+fn create_private(vmid: u32) -> io::Result<(PathBuf, File)> {
+    let path = private_path(vmid, ".vv");
+
+    let file = File::options()
+        .write(true)
+        .create_new(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .mode(0o600)
+        .open(&path)?;
+
+    Ok((path, file))
+}
+
+/// Writes virt-viewer's settings with the `monitor-mapping` to `config_dir`.
+fn write_settings(config_dir: &Path, mapping: &str) -> io::Result<()> {
+    let dir = config_dir.join("virt-viewer");
+    fs::create_dir(&dir)?;
+
+    let mut file = File::options()
+        .write(true)
+        .create_new(true)
+        .open(dir.join("settings"))?;
+    writeln!(file, "[{FALLBACK_GROUP}]")?;
+    writeln!(file, "monitor-mapping={}", escape(mapping).expose())
+}
+
+/// Runs `command` for the console of a container without waiting for it to exit, or xterm if
+/// none is configured, removing `dir` once it exits.
+///
+/// The console is relayed to the Unix socket at `socket_path`, so the command isn't handed any
+/// credentials.
+pub fn launch_terminal(
+    dir: PathBuf,
+    socket_path: &Path,
+    guest: &Guest,
+    command: &[String],
+) -> io::Result<()> {
+    let command: Vec<&str> = command.iter().map(String::as_str).collect();
+    let (program, args) = command
+        .split_first()
+        .unwrap_or_else(|| (&TERMINAL[0], &TERMINAL[1..]));
+
+    let id = guest.vmid.to_string();
+    let socket_path = socket_path.to_string_lossy();
+    let placeholders = [
+        ("{node}", guest.node.as_str()),
+        ("{vmid}", id.as_str()),
+        ("{name}", guest.name.as_str()),
+        ("{socket}", &socket_path),
+    ];
+    let args = args.iter().map(|arg| {
+        placeholders
+            .iter()
+            .fold((*arg).to_owned(), |arg, (placeholder, value)| {
+                arg.replace(placeholder, value)
+            })
+    });
+
+    spawn(Command::new(program).args(args), Some(dir))
+}
+
+/// Options of the `[virt-viewer]` section of the .vv file for `config`.
+///
+/// They're all kept as secrets, since one of them is the password.
+fn options(config: &ConnectionConfig) -> BTreeMap<String, Secret> {
+    // The file holds the password, so it's deleted unless Proxmox says otherwise
+    let (password, options) = match config {
+        ConnectionConfig::Spice(config) => (
+            &config.password,
+            vec![
+                ("type", Some(config.conn_type.clone())),
+                ("host", Some(config.host.clone())),
+                ("proxy", Some(config.proxy.clone())),
+                ("tls-port", Some(config.tls_port.to_string())),
+                ("ca", config.ca.clone()),
+                ("host-subject", config.host_subject.clone()),
+                ("secure-attention", config.secure_attention.clone()),
+                ("release-cursor", config.release_cursor.clone()),
+                ("toggle-fullscreen", config.toggle_fullscreen.clone()),
+                ("title", config.title.clone()),
+                (
+                    "delete-this-file",
+                    Some(config.delete_this_file.unwrap_or(1).to_string()),
+                ),
+            ],
+        ),
+        ConnectionConfig::Vnc(config) => (
+            &config.password,
+            vec![
+                ("type", Some("vnc".to_owned())),
+                ("host", Some(config.host.clone())),
+                ("port", Some(config.port.to_string())),
+                ("delete-this-file", Some("1".to_owned())),
+            ],
+        ),
+    };
+
+    options
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.to_owned(), Secret::new(value?))))
+        .chain([("password".to_owned(), password.clone())])
+        .collect()
+}
+
+fn profile_options(profile: &ViewerProfile) -> BTreeMap<String, Secret> {
+    let flags = [
+        ("fullscreen", profile.fullscreen),
+        ("enable-usb-autoshare", profile.usb_auto_share),
+        ("enable-smartcard", profile.smartcard),
+    ];
+    let disabled_channels = match profile.audio {
+        Audio::Full => None,
+        Audio::PlaybackOnly => Some("record"),
+        Audio::Off => Some("playback;record"),
+    };
+
+    flags
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(key, _)| (key, "1".to_owned()))
+        .chain(disabled_channels.map(|channels| ("disable-channels", channels.to_owned())))
+        .map(|(key, value)| (key.to_owned(), Secret::new(value)))
+        .collect()
+}
+
+/// Escapes `value` the way key files of `GLib`, which .vv files are, expect.
+fn escape(value: &str) -> Secret {
+    // Room for every character to be escaped, so no copy of the value is left behind by growing
+    let mut escaped = String::with_capacity(value.len() * 2);
+
+    for (idx, char) in value.chars().enumerate() {
+        match char {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            // Leading spaces would be trimmed otherwise
+            ' ' if idx == 0 => escaped.push_str("\\s"),
+            char => escaped.push(char),
+        }
+    }
+
+    Secret::new(escaped)
+}
+
+/// Starts `command`, removing `leftover` once it exits.
+fn spawn(command: &mut Command, leftover: Option<PathBuf>) -> io::Result<()> {
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            if let Some(leftover) = leftover {
+                let _ = fs::remove_dir_all(leftover);
+            }
+            return Err(err);
+        }
+    };
+
+    // Reap the child once it exits so it doesn't linger as a zombie
+    thread::spawn(move || {
+        let _ = child.wait();
+
+        if let Some(leftover) = leftover {
+            let _ = fs::remove_dir_all(leftover);
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_keeps_plain_values() {
+        assert_eq!(escape("spice://pve1:3128").expose(), "spice://pve1:3128");
+        assert_eq!(escape("").expose(), "");
+    }
+
+    #[test]
+    fn escape_control_characters_and_backslashes() {
+        assert_eq!(
+            escape("-----BEGIN\nline\r\tend\\").expose(),
+            "-----BEGIN\\nline\\r\\tend\\\\"
+        );
+    }
+
+    #[test]
+    fn escape_only_leading_space() {
+        assert_eq!(escape("  a b").expose(), "\\s a b");
+    }
+}